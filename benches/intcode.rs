@@ -0,0 +1,145 @@
+//! Rough timings for the intcode VM.  Run with `cargo bench`.
+//!
+//! The memory benchmarks compare the paged `Memory` against the plain
+//! `HashMap` the VM used to store its memory in.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use aoc::get_input_from_file;
+use aoc::intcode::Program;
+use aoc::intcode::memory::Memory;
+
+const RUNS: u32 = 5;
+
+fn load(day: &str) -> Vec<i64> {
+    let filename = format!("{}/inputs/{}.txt", env!("CARGO_MANIFEST_DIR"), day);
+    get_input_from_file(&filename)
+        .trim()
+        .split(',')
+        .map(|x| x.trim().parse().expect("NaN"))
+        .collect()
+}
+
+/// Runs `f` a few times and reports the fastest run.
+fn bench<F: FnMut() -> i64>(name: &str, mut f: F) {
+    let mut best = Duration::from_secs(u64::MAX);
+    let mut result = 0;
+
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        result = f();
+        best = best.min(start.elapsed());
+    }
+
+    println!("{:<36} {:>10.2?}  (result {})", name, best, result);
+}
+
+fn memory_access_pattern() -> Vec<usize> {
+    // Mostly low addresses, like program code and data, with some stack
+    // accesses further up
+    (0..1_000_000).map(|i| (i * 7919) % 4096).collect()
+}
+
+fn main() {
+    let day09 = load("09");
+    let day19 = load("19");
+    let day07 = load("07");
+    let addrs = memory_access_pattern();
+
+    bench("memory: Memory read/write", || {
+        let mut memory = Memory::new(&day09);
+        let mut sum = 0;
+        for &addr in &addrs {
+            sum += memory.read(addr);
+            memory.write(addr, sum & 0xff);
+        }
+        sum
+    });
+
+    bench("memory: HashMap read/write", || {
+        let mut memory: HashMap<usize, i64> = day09.iter().cloned().enumerate().collect();
+        let mut sum = 0;
+        for &addr in &addrs {
+            sum += memory.get(&addr).cloned().unwrap_or(0);
+            memory.insert(addr, sum & 0xff);
+        }
+        sum
+    });
+
+    bench("memory: Memory clone + write", || {
+        let pristine = Memory::new(&day19);
+        let mut sum = 0;
+        for i in 0..10_000 {
+            let mut memory = pristine.clone();
+            memory.write(i % 500, 1);
+            sum += memory.read(i % 500);
+        }
+        sum
+    });
+
+    bench("memory: HashMap clone + write", || {
+        let pristine: HashMap<usize, i64> = day19.iter().cloned().enumerate().collect();
+        let mut sum = 0;
+        for i in 0..10_000 {
+            let mut memory = pristine.clone();
+            memory.insert(i % 500, 1);
+            sum += memory[&(i % 500)];
+        }
+        sum
+    });
+
+    bench("day 09 part 2", || {
+        let mut program = Program::new(&day09);
+        program.set_input(2);
+        program.pause_on_output().unwrap().unwrap()
+    });
+
+    bench("day 19 part 1 (2500 fresh VMs)", || {
+        let pristine = Program::new(&day19);
+        let mut count = 0;
+        for x in 0..50 {
+            for y in 0..50 {
+                let mut program = pristine.clone();
+                program.set_input(x);
+                program.set_input(y);
+                count += program.pause_on_output().unwrap().unwrap();
+            }
+        }
+        count
+    });
+
+    bench("day 07 part 1 (120 permutations)", || {
+        let mut best = 0;
+        let mut phases = [0, 1, 2, 3, 4];
+        for _ in 0..120 {
+            let mut signal = 0;
+            for &phase in &phases {
+                let mut program = Program::new(&day07);
+                program.set_input(phase);
+                program.set_input(signal);
+                signal = program.pause_on_output().unwrap().unwrap();
+            }
+            best = best.max(signal);
+            next_permutation(&mut phases);
+        }
+        best
+    });
+}
+
+fn next_permutation(values: &mut [i64]) {
+    let mut i = values.len() - 1;
+    while i > 0 && values[i - 1] >= values[i] {
+        i -= 1;
+    }
+    if i == 0 {
+        values.reverse();
+        return;
+    }
+
+    let mut j = values.len() - 1;
+    while values[j] <= values[i - 1] {
+        j -= 1;
+    }
+    values.swap(i - 1, j);
+    values[i..].reverse();
+}
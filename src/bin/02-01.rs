@@ -10,6 +10,6 @@ fn main() {
     initial_memory[1] = 12;
     initial_memory[2] = 2;
     let mut program = Program::new(&initial_memory);
-    program.run();
+    program.run().unwrap();
     println!("{}", program.read(0));
 }
@@ -1,28 +1,23 @@
-use std::process;
 use aoc::get_input;
 use aoc::intcode::Program;
 
 fn main() {
     let input = get_input();
-    let mut initial_memory: Vec<i64> = input
+    let initial_memory: Vec<i64> = input
         .split(',')
         .map(|x| x.parse().expect("NaN"))
         .collect();
 
-    for noun in 0..100 {
-        for verb in 0..100 {
-            initial_memory[1] = noun;
-            initial_memory[2] = verb;
-            let mut program = Program::new(&initial_memory);
-            program.run();
+    // The result is a linear function of the noun and verb, so work it out
+    // once and solve for them instead of trying every pair
+    let mut symbolic = Program::new(&initial_memory).symbolic();
+    symbolic.set_symbol(1, "noun");
+    symbolic.set_symbol(2, "verb");
+    let paths = symbolic.run().unwrap();
+    let result = paths[0].read(0);
 
-            if program.read(0) == 19690720 {
-                let res = 100 * noun + verb;
-                println!("{}", res);
-                process::exit(0);
-            }
-        }
+    match result.solve(19690720, &[("noun", 0..100), ("verb", 0..100)]) {
+        Some(values) => println!("{}", 100 * values[0] + values[1]),
+        None => println!("No result found"),
     }
-
-    println!("No result found");
 }
@@ -9,6 +9,6 @@ fn main() {
         .collect();
     let mut program = Program::new(&initial_memory);
     program.set_input(1);
-    program.run();
+    program.run().unwrap();
     println!("{}", program.pop_output().unwrap());
 }
@@ -32,7 +32,7 @@ impl Point {
 
 fn main() {
     let input = get_input();
-    let initial_memory = input
+    let initial_memory: Vec<i64> = input
         .split(',')
         .map(|x| x.parse().expect("NaN"))
         .collect();
@@ -48,12 +48,12 @@ fn main() {
             None => program.set_input(0),
         }
 
-        match program.pause_on_output() {
+        match program.pause_on_output().unwrap() {
             Some(color) => { grid.insert((pos.x, pos.y), color); }
             None => break,
         }
 
-        match program.pause_on_output() {
+        match program.pause_on_output().unwrap() {
             Some(dir) => {
                 match dir {
                     0 => direction.turn_left(),
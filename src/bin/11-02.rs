@@ -75,7 +75,7 @@ fn visualize_grid(grid: &HashMap<(i64, i64), i64>) -> String {
 
 fn main() {
     let input = get_input();
-    let initial_memory = input
+    let initial_memory: Vec<i64> = input
         .split(',')
         .map(|x| x.parse().expect("NaN"))
         .collect();
@@ -93,12 +93,12 @@ fn main() {
             None => program.set_input(0),
         }
 
-        match program.pause_on_output() {
+        match program.pause_on_output().unwrap() {
             Some(color) => { grid.insert((pos.x, pos.y), color); }
             None => break,
         }
 
-        match program.pause_on_output() {
+        match program.pause_on_output().unwrap() {
             Some(dir) => {
                 match dir {
                     0 => direction.turn_left(),
@@ -4,7 +4,7 @@ use aoc::intcode::Program;
 
 fn main() {
     let input = get_input();
-    let initial_memory = input
+    let initial_memory: Vec<i64> = input
         .split(',')
         .map(|x| x.parse().expect("NaN"))
         .collect();
@@ -12,10 +12,7 @@ fn main() {
     let mut grid = HashMap::new();
 
     while program.is_running() {
-        if let Some(x) = program.pause_on_output() {
-            let y = program.pause_on_output().unwrap();
-            let tile = program.pause_on_output().unwrap();
-
+        if let [x, y, tile] = program.run_until_output_count(3).unwrap()[..] {
             grid.insert((x, y), tile);
         }
     }
@@ -3,7 +3,8 @@ use std::thread::sleep;
 use std::time::Duration;
 use pancurses;
 use aoc::get_input;
-use aoc::intcode::Program;
+use aoc::intcode::{Program, RunState};
+use aoc::intcode::patch::Patch;
 
 fn render(window: &pancurses::Window, grid: &mut HashMap<(i64, i64), i64>, score: i64) {
     let board_offset = 1;
@@ -70,13 +71,14 @@ fn get_joystick(grid: &HashMap<(i64, i64), i64>) -> i64 {
 
 fn main() {
     let input = get_input();
-    let mut initial_memory: Vec<i64> = input
+    let initial_memory: Vec<i64> = input
         .trim()
         .split(',')
         .map(|x| x.parse().expect("NaN"))
         .collect();
-    initial_memory[0] = 2;
+    let free_play = Patch::parse(include_str!("../../patches/13-free-play.patch")).unwrap();
     let mut program = Program::new(&initial_memory);
+    program.apply_patch(&free_play).unwrap();
     let mut score = 0;
     let mut grid = HashMap::new();
 
@@ -92,35 +94,34 @@ fn main() {
     pancurses::init_pair(7, pancurses::COLOR_YELLOW, pancurses::COLOR_BLACK);
     pancurses::curs_set(0);
 
-    while program.is_running() {
-        if program.needs_input() {
-            let joystick = get_joystick(&grid);
-            program.set_input(joystick);
-        }
-
-        program.step();
-
-        if program.has_output() {
-            let x = program.pause_on_output().unwrap();
-            let y = program.pause_on_output().unwrap();
-
-            if x == -1 && y == 0 {
-                score = program.pause_on_output().unwrap();
-            } else {
-                let mut tile = program.pause_on_output().unwrap();
-                if let Some(2) = grid.get(&(x, y)) {
-                    if tile != 2 {
-                        tile = 5;
+    loop {
+        match program.run_until_blocked().unwrap() {
+            RunState::NeedsInput => {
+                let joystick = get_joystick(&grid);
+                program.set_input(joystick);
+            },
+            RunState::Output(x) => {
+                let outputs = program.run_until_output_count(2).unwrap();
+                let (y, mut tile) = (outputs[0], outputs[1]);
+
+                if x == -1 && y == 0 {
+                    score = tile;
+                } else {
+                    if let Some(2) = grid.get(&(x, y)) {
+                        if tile != 2 {
+                            tile = 5;
+                        }
                     }
+                    grid.insert((x, y), tile);
+                }
+
+                let values: Vec<i64> = grid.values().map(|v| *v).collect();
+                if values.contains(&3) && values.contains(&4) {
+                    render(&window, &mut grid, score);
+                    sleep(Duration::from_millis(50));
                 }
-                grid.insert((x, y), tile);
-            }
-
-            let values: Vec<i64> = grid.values().map(|v| *v).collect();
-            if values.contains(&3) && values.contains(&4) {
-                render(&window, &mut grid, score);
-                sleep(Duration::from_millis(50));
-            }
+            },
+            RunState::Halted => break,
         }
     }
 
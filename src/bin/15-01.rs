@@ -260,7 +260,7 @@ fn plot_route(window: &pancurses::Window, route: &Vec<Point>, droid: &Point, gri
 
 fn main() {
     let input = get_input();
-    let initial_memory = input
+    let initial_memory: Vec<i64> = input
         .split(',')
         .map(|x| x.parse().expect("NaN"))
         .collect();
@@ -302,7 +302,7 @@ fn main() {
         program.set_input(direction);
         let immediate_target = droid.point_in_direction(direction);
 
-        match program.pause_on_output() {
+        match program.pause_on_output().unwrap() {
             Some(0) => {
                 grid.insert(immediate_target, Tile::Wall);
             },
@@ -318,7 +318,7 @@ fn sleep() {
 
 fn main() {
     let input = get_input();
-    let initial_memory = input
+    let initial_memory: Vec<i64> = input
         .split(',')
         .map(|x| x.parse().expect("NaN"))
         .collect();
@@ -365,7 +365,7 @@ fn main() {
         program.set_input(direction);
         let immediate_target = droid.point_in_direction(direction);
 
-        match program.pause_on_output() {
+        match program.pause_on_output().unwrap() {
             Some(0) => {
                 grid.insert(immediate_target, Tile::Wall);
             },
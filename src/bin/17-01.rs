@@ -75,7 +75,7 @@ fn get_intersection_sum(grid: &HashMap<Point, Tile>) -> i32 {
 }
 
 fn main() {
-    let memory = get_input()
+    let memory: Vec<i64> = get_input()
         .trim()
         .split(',')
         .map(|x| x.trim().parse().expect("NaN"))
@@ -86,7 +86,7 @@ fn main() {
     let mut pos = Point { x: 0, y: 0 };
 
     while p.is_running() {
-        if let Some(ch) = p.pause_on_output() {
+        if let Some(ch) = p.pause_on_output().unwrap() {
             let out = (ch as u8) as char;
             match out {
                 '\n' => {
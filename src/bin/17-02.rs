@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use aoc::get_input;
-use aoc::intcode::Program;
+use aoc::intcode::ascii::AsciiProgram;
+use aoc::intcode::patch::Patch;
 
 enum Direction {
     Left,
@@ -33,65 +34,63 @@ impl Point {
 }
 
 fn main() {
-    let mut memory: Vec<i64> = get_input()
+    let memory: Vec<i64> = get_input()
         .trim()
         .split(',')
         .map(|x| x.trim().parse().expect("NaN"))
         .collect();
+    let wake_up = Patch::parse(include_str!("../../patches/17-wake-up.patch")).unwrap();
 
-    memory[0] = 2;
-
-    let mut p = Program::new(&memory);
+    let mut p = AsciiProgram::new(&memory);
+    p.program_mut().apply_patch(&wake_up).unwrap();
     let mut grid = HashMap::new();
     let mut pos = Point { x: 0, y: 0 };
 
-    let input = vec![
-        "A,B,A,C,B,C,A,B,A,C\n",
-        "R,10,L,8,R,10,R,4\n",
-        "L,6,L,6,R,10\n",
-        "L,6,R,12,R,12,R,10\n",
-        "y\n",
-    ].join("");
+    let script = vec![
+        "A,B,A,C,B,C,A,B,A,C",
+        "R,10,L,8,R,10,R,4",
+        "L,6,L,6,R,10",
+        "L,6,R,12,R,12,R,10",
+        "y",
+    ];
 
-    for ch in input.chars() {
-        p.set_input(ch as i64);
+    for line in script {
+        p.send_line(line);
     }
 
-    while p.is_running() {
-        if let Some(ch) = p.pause_on_output() {
-            if ch > 255 {
-                println!("Dust collected: {}", ch);
-                continue;
-            }
+    let response = p.read_until_prompt().unwrap();
 
-            let out = (ch as u8) as char;
-            match out {
-                '\n' => {
-                    pos.step(&Direction::Down);
-                    pos.x = -1; // Incremented after the match block
-                },
-                '#' => {
-                    grid.insert(pos, Tile::Scaffold);
-                },
-                '.' => {
-                    grid.insert(pos, Tile::Space);
-                },
-                '<' => {
-                    grid.insert(pos, Tile::Droid(Direction::Left));
-                },
-                '>' => {
-                    grid.insert(pos, Tile::Droid(Direction::Right));
-                },
-                '^' => {
-                    grid.insert(pos, Tile::Droid(Direction::Up));
-                },
-                'V' => {
-                    grid.insert(pos, Tile::Droid(Direction::Down));
-                },
-                _ => (),
-            }
-            pos.x += 1;
-            print!("{}", out);
+    for out in response.text.chars() {
+        match out {
+            '\n' => {
+                pos.step(&Direction::Down);
+                pos.x = -1; // Incremented after the match block
+            },
+            '#' => {
+                grid.insert(pos, Tile::Scaffold);
+            },
+            '.' => {
+                grid.insert(pos, Tile::Space);
+            },
+            '<' => {
+                grid.insert(pos, Tile::Droid(Direction::Left));
+            },
+            '>' => {
+                grid.insert(pos, Tile::Droid(Direction::Right));
+            },
+            '^' => {
+                grid.insert(pos, Tile::Droid(Direction::Up));
+            },
+            'V' => {
+                grid.insert(pos, Tile::Droid(Direction::Down));
+            },
+            _ => (),
         }
+        pos.x += 1;
+        print!("{}", out);
+    }
+
+    for value in response.values {
+        println!("Dust collected: {}", value);
     }
 }
@@ -7,16 +7,16 @@ fn main() {
         .split(',')
         .map(|x| x.trim().parse().expect("NaN"))
         .collect();
-    let mut p = Program::new(&memory);
+    let pristine = Program::new(&memory);
     let mut count = 0;
 
     for x in 0..50 {
         for y in 0..50 {
-            p.reset(&memory);
+            let mut p = pristine.clone();
             p.set_input(x);
             p.set_input(y);
 
-            count += p.pause_on_output().unwrap();
+            count += p.pause_on_output().unwrap().unwrap();
         }
     }
 
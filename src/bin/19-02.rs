@@ -3,17 +3,19 @@ use aoc::get_input;
 use aoc::intcode::Program;
 
 fn main() {
-    let memory = get_input()
+    let memory: Vec<i64> = get_input()
         .trim()
         .split(',')
         .map(|x| x.trim().parse().expect("NaN"))
         .collect();
 
+    let pristine = Program::new(&memory);
+
     let scan = |x, y| {
-        let mut p = Program::new(&memory);
+        let mut p = pristine.clone();
         p.set_input(x);
         p.set_input(y);
-        p.pause_on_output().unwrap()
+        p.pause_on_output().unwrap().unwrap()
     };
 
     let height = |x, y_start, min_height| {
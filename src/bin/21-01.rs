@@ -1,37 +1,33 @@
 use aoc::get_input;
-use aoc::intcode::Program;
+use aoc::intcode::ascii::AsciiProgram;
 
 fn main() {
-    let memory = get_input()
+    let memory: Vec<i64> = get_input()
         .trim()
         .split(',')
         .map(|x| x.trim().parse().expect("NaN"))
         .collect();
 
-    let mut p = Program::new(&memory);
+    let mut p = AsciiProgram::new(&memory);
 
-    let input = vec![
+    let script = vec![
         "NOT A J",
         "NOT B T",
         "OR T J",
         "NOT C T",
         "OR T J",
         "AND D J",
-        "WALK\n"
-    ].join("\n");
+        "WALK",
+    ];
 
-    for ch in input.chars() {
-        p.set_input(ch as i64);
+    for line in script {
+        p.send_line(line);
     }
 
-    while p.is_running() {
-        if let Some(ch) = p.pause_on_output() {
-            if ch > 255 {
-                println!("{}", ch);
-                continue;
-            }
-            let out = (ch as u8) as char;
-            print!("{}", out);
-        }
+    let response = p.read_until_prompt().unwrap();
+    print!("{}", response.text);
+
+    for value in response.values {
+        println!("{}", value);
     }
 }
@@ -1,57 +1,22 @@
-use std::process;
-use aoc::intcode::Program;
+use std::sync::{Arc, Mutex};
+use aoc::intcode::router::{Nat, Network};
 use aoc::get_input;
 
 fn main() {
-    let memory = get_input()
+    let memory: Vec<i64> = get_input()
         .split(',')
         .map(|x| x.trim().parse().expect("NaN"))
         .collect();
-    let mut programs = vec![];
-    let mut queues = vec![];
-
-    for addr in 0..50 {
-        let mut p = Program::new(&memory);
-
-        while !p.needs_input() {
-            p.step();
-        }
-        p.set_input(addr);
-        p.step();
-
-        programs.push(p);
-        queues.push(vec![]);
-    }
+    let mut network = Network::new(&memory, 50);
+    let nat = Arc::new(Mutex::new(Nat::new()));
+    network.add_handler(255, nat.clone());
 
     loop {
-        for addr in 0..50 {
-            let p = &mut programs[addr];
-            if p.needs_input() {
-                if queues[addr].is_empty() {
-                    p.set_input(-1);
-                } else {
-                    let (x, y) = queues[addr].pop().unwrap();
-                    p.set_input(x);
-                    p.set_input(y);
-                    p.step();
-                    p.step();
-                }
-            }
-
-            p.step();
-
-            if p.has_output() {
-                let dst = p.pause_on_output().unwrap() as usize;
-                let x = p.pause_on_output().unwrap();
-                let y = p.pause_on_output().unwrap();
+        network.step().unwrap();
 
-                if dst == 255 {
-                    println!("{}", y);
-                    process::exit(0);
-                } else {
-                    queues[dst].insert(0, (x, y));
-                }
-            }
+        if let Some(packet) = nat.lock().unwrap().received().first() {
+            println!("{}", packet.y);
+            break;
         }
     }
 }
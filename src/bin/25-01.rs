@@ -24,7 +24,7 @@ struct SaveState {
 }
 
 impl Game {
-    pub fn new(memory: &Vec<i64>) -> Game {
+    pub fn new(memory: &[i64]) -> Game {
         let program = Program::new(memory);
         let mut save_states = vec![];
         save_states.push(SaveState {
@@ -81,7 +81,7 @@ impl Game {
     }
 
     pub fn step(&mut self) {
-        self.program.step();
+        self.program.step().unwrap();
     }
 
     pub fn bruteforce(&mut self, direction: &str) {
@@ -150,9 +150,7 @@ impl Game {
     }
 
     fn send_command(&mut self, command: &str) {
-        for ch in command.chars() {
-            self.program.set_input(ch as i64);
-        }
+        self.program.push_ascii(command);
 
         // finish with a newline
         self.program.set_input(10);
@@ -293,7 +291,7 @@ impl Game {
 fn main() {
     let args: Vec<_> = env::args().collect();
     let filename = &args[1];
-    let memory = get_input_from_file(filename)
+    let memory: Vec<i64> = get_input_from_file(filename)
         .split(',')
         .map(|x| x.trim().parse().expect("NaN"))
         .collect();
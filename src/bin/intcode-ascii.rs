@@ -0,0 +1,53 @@
+use std::env;
+use std::io::{self, Write};
+use std::process;
+use aoc::get_input_from_file;
+use aoc::intcode::ascii::AsciiProgram;
+
+fn main() {
+    let args: Vec<_> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("usage: intcode-ascii <program> [script]");
+        process::exit(1);
+    }
+
+    let memory: Vec<i64> = get_input_from_file(&args[1])
+        .trim()
+        .split(',')
+        .map(|x| x.trim().parse().expect("NaN"))
+        .collect();
+    let mut program = AsciiProgram::new(&memory);
+
+    // Lines from an optional script are sent before handing over to the user
+    if let Some(script) = args.get(2) {
+        for line in get_input_from_file(script).lines() {
+            program.send_line(line);
+        }
+    }
+
+    loop {
+        let response = match program.read_until_prompt() {
+            Ok(response) => response,
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            },
+        };
+
+        print!("{}", response.text);
+        for value in response.values {
+            println!("{}", value);
+        }
+
+        if !program.is_running() {
+            break;
+        }
+
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => program.send_line(line.trim_end_matches(&['\r', '\n'][..])),
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use std::process;
+use aoc::get_input;
+use aoc::intcode::asm;
+
+fn main() {
+    match asm::assemble(&get_input()) {
+        Ok(memory) => {
+            let words: Vec<String> = memory.iter().map(|w| w.to_string()).collect();
+            println!("{}", words.join(","));
+        },
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        },
+    }
+}
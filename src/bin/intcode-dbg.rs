@@ -0,0 +1,45 @@
+use std::env;
+use std::io::{self, Write};
+use aoc::get_input_from_file;
+use aoc::intcode::debugger::Debugger;
+
+fn main() {
+    let args: Vec<_> = env::args().collect();
+    let filename = &args[1];
+    let memory: Vec<i64> = get_input_from_file(filename)
+        .trim()
+        .split(',')
+        .map(|x| x.trim().parse().expect("NaN"))
+        .collect();
+    let mut debugger = Debugger::new(&memory);
+    let mut last_command = String::new();
+
+    println!("{}", debugger.location());
+
+    loop {
+        print!("(dbg) ");
+        io::stdout().flush().unwrap();
+
+        let mut input = String::new();
+        match io::stdin().read_line(&mut input) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => (),
+        }
+
+        // an empty line repeats the previous command
+        let command = match input.trim() {
+            "" => last_command.clone(),
+            command => String::from(command),
+        };
+
+        if command == "q" || command == "quit" {
+            break;
+        }
+
+        let output = debugger.execute(&command);
+        if !output.is_empty() {
+            println!("{}", output);
+        }
+        last_command = command;
+    }
+}
@@ -0,0 +1,12 @@
+use aoc::get_input;
+use aoc::intcode::disasm;
+
+fn main() {
+    let memory: Vec<i64> = get_input()
+        .trim()
+        .split(',')
+        .map(|x| x.trim().parse().expect("NaN"))
+        .collect();
+
+    print!("{}", disasm::listing(&memory));
+}
@@ -0,0 +1,37 @@
+use std::env;
+use std::process;
+use aoc::get_input;
+use aoc::intcode::Program;
+use aoc::intcode::patch::Patch;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("usage: intcode-patch <patch-file>... < program");
+        process::exit(1);
+    }
+
+    let memory: Vec<i64> = get_input()
+        .trim()
+        .split(',')
+        .map(|x| x.trim().parse().expect("NaN"))
+        .collect();
+    let mut program = Program::new(&memory);
+    let mut len = memory.len();
+
+    for filename in &args[1..] {
+        let result = Patch::from_file(filename).and_then(|patch| {
+            program.apply_patch(&patch)?;
+            len = len.max(patch.extent());
+            Ok(())
+        });
+
+        if let Err(err) = result {
+            eprintln!("{}: {}", filename, err);
+            process::exit(1);
+        }
+    }
+
+    let words: Vec<String> = (0..len).map(|addr| program.read(addr).to_string()).collect();
+    println!("{}", words.join(","));
+}
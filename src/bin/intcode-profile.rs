@@ -0,0 +1,52 @@
+use std::env;
+use std::process;
+use std::time::Instant;
+use aoc::get_input;
+use aoc::intcode::Program;
+
+const USAGE: &str = "\
+usage: intcode-profile [--top N] [inputs...] < program
+
+Runs the program until it halts or runs out of input, and prints where
+it spent its time.  Inputs that aren't numbers are sent as ASCII lines.";
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut top = 10;
+
+    if args.first().map(String::as_str) == Some("--top") {
+        top = match args.get(1).and_then(|n| n.parse().ok()) {
+            Some(n) => n,
+            None => {
+                eprintln!("{}", USAGE);
+                process::exit(1);
+            },
+        };
+        args.drain(..2);
+    }
+
+    let memory: Vec<i64> = get_input()
+        .trim()
+        .split(',')
+        .map(|x| x.trim().parse().expect("NaN"))
+        .collect();
+    let mut program = Program::new(&memory);
+
+    for input in &args {
+        match input.parse() {
+            Ok(value) => program.set_input(value),
+            Err(_) => program.push_ascii(&format!("{}\n", input)),
+        }
+    }
+
+    program.start_profiling();
+    let started = Instant::now();
+    if let Err(err) = program.run() {
+        eprintln!("{}", err);
+    }
+    let elapsed = started.elapsed();
+
+    let profile = program.stop_profiling().unwrap();
+    println!("Ran for {:.3}s", elapsed.as_secs_f64());
+    print!("{}", profile.report(&program, top));
+}
@@ -0,0 +1,78 @@
+use std::env;
+use std::process;
+use std::str::FromStr;
+use num::BigInt;
+use aoc::get_input;
+use aoc::intcode::Program;
+use aoc::intcode::cell::Cell;
+use aoc::intcode::host::HostCalls;
+
+const USAGE: &str = "\
+usage: intcode-run [--i128 | --bigint] [--checked] [--host] [inputs...] < program
+
+Runs the program until it halts or runs out of input, and prints its
+outputs, one per line.  Cells are i64 unless --i128 or --bigint is
+given.  With --checked, an add or multiply that overflows stops the
+program with an error instead of wrapping.  With --host, opcode 10 makes
+the standard host calls (see src/intcode/host.rs).";
+
+struct Options {
+    checked: bool,
+    host: bool,
+}
+
+fn run<T: Cell + FromStr>(source: &str, inputs: &[String], options: &Options) {
+    let memory: Vec<T> = source
+        .trim()
+        .split(',')
+        .map(|x| x.trim().parse().ok().expect("NaN"))
+        .collect();
+    let mut program = Program::new(&memory);
+    program.set_checked(options.checked);
+    if options.host {
+        program.set_host_calls(HostCalls::standard());
+    }
+
+    for input in inputs {
+        match input.parse() {
+            Ok(value) => program.set_input(value),
+            Err(_) => program.push_ascii(&format!("{}\n", input)),
+        }
+    }
+
+    let result = program.run();
+    for output in program.drain_outputs() {
+        println!("{}", output);
+    }
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+fn main() {
+    let mut cells = "i64";
+    let mut options = Options { checked: false, host: false };
+    let mut inputs = vec![];
+
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--i128" => cells = "i128",
+            "--bigint" => cells = "bigint",
+            "--checked" => options.checked = true,
+            "--host" => options.host = true,
+            "-h" | "--help" => {
+                eprintln!("{}", USAGE);
+                process::exit(1);
+            },
+            _ => inputs.push(arg),
+        }
+    }
+
+    let source = get_input();
+    match cells {
+        "i128" => run::<i128>(&source, &inputs, &options),
+        "bigint" => run::<BigInt>(&source, &inputs, &options),
+        _ => run::<i64>(&source, &inputs, &options),
+    }
+}
@@ -0,0 +1,148 @@
+use std::env;
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use aoc::get_input;
+use aoc::intcode::{Program, VmError};
+use aoc::intcode::device::{Framebuffer, Random, Timer};
+
+const USAGE: &str = "\
+usage: intcode-screen [--size WxH] [inputs...] < program
+
+Runs the program with a framebuffer mapped at address 10000, drawn to
+the terminal as it changes.  Cell (x, y) is at 10000 + y * width + x,
+and values 1 to 4 are drawn like the day 13 tiles: wall, block, paddle
+and ball.  Address 9998 reads as a millisecond timer and 9999 as a
+random number.  When the program wants input and none is queued, the
+next key pressed is sent.  The default size is 40x24.";
+
+const FRAMEBUFFER: usize = 10000;
+const TIMER: usize = 9998;
+const RANDOM: usize = 9999;
+
+/// Instructions to run between checks for something to draw
+const BATCH: usize = 1024;
+const FRAME_TIME: Duration = Duration::from_millis(20);
+
+fn render(window: &pancurses::Window, framebuffer: &Framebuffer) {
+    for (y, row) in framebuffer.rows().enumerate() {
+        for (x, &tile) in row.iter().enumerate() {
+            let output = match tile {
+                0 => "   ",
+                1 => "███",
+                2 => "▐█▌",
+                3 => "▀▀▀",
+                4 => " ● ",
+                _ => " ? ",
+            };
+
+            let attr = pancurses::COLOR_PAIR(tile.clamp(0, 4) as u32);
+            window.attron(attr);
+            window.mvprintw(y as i32, (x * 3) as i32, output);
+            window.attroff(attr);
+        }
+    }
+    window.refresh();
+}
+
+fn parse_size(size: &str) -> Option<(usize, usize)> {
+    let (width, height) = size.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+fn run(
+    program: &mut Program,
+    window: &pancurses::Window,
+    framebuffer: &Mutex<Framebuffer>,
+) -> Result<(), VmError> {
+    let mut last_frame = Instant::now();
+
+    while program.is_running() {
+        if program.needs_input() {
+            render(window, &framebuffer.lock().unwrap());
+            let key = loop {
+                if let Some(pancurses::Input::Character(ch)) = window.getch() {
+                    break ch;
+                }
+            };
+            program.set_input(key as i64);
+        }
+
+        for _ in 0..BATCH {
+            if !program.is_running() || program.needs_input() {
+                break;
+            }
+            program.step()?;
+        }
+
+        let mut framebuffer = framebuffer.lock().unwrap();
+        if last_frame.elapsed() >= FRAME_TIME && framebuffer.take_dirty() {
+            render(window, &framebuffer);
+            last_frame = Instant::now();
+        }
+    }
+
+    render(window, &framebuffer.lock().unwrap());
+    Ok(())
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut size = (40, 24);
+
+    if args.first().map(String::as_str) == Some("--size") {
+        size = match args.get(1).and_then(|size| parse_size(size)) {
+            Some(size) => size,
+            None => {
+                eprintln!("{}", USAGE);
+                process::exit(1);
+            },
+        };
+        args.drain(..2);
+    }
+
+    let memory: Vec<i64> = get_input()
+        .trim()
+        .split(',')
+        .map(|x| x.trim().parse().expect("NaN"))
+        .collect();
+    let mut program = Program::new(&memory);
+
+    for input in &args {
+        match input.parse() {
+            Ok(value) => program.set_input(value),
+            Err(_) => program.push_ascii(&format!("{}\n", input)),
+        }
+    }
+
+    let framebuffer = Arc::new(Mutex::new(Framebuffer::new(size.0, size.1)));
+    let cells = framebuffer.lock().unwrap().len();
+    program.map_device(FRAMEBUFFER..FRAMEBUFFER + cells, framebuffer.clone());
+    program.map_device(TIMER..TIMER + 1, Arc::new(Mutex::new(Timer::new())));
+    program.map_device(RANDOM..RANDOM + 1, Arc::new(Mutex::new(Random::new())));
+
+    let window = pancurses::initscr();
+    pancurses::start_color();
+    pancurses::init_pair(0, pancurses::COLOR_WHITE, pancurses::COLOR_BLACK);
+    pancurses::init_pair(1, pancurses::COLOR_WHITE, pancurses::COLOR_BLACK);
+    pancurses::init_pair(2, pancurses::COLOR_YELLOW, pancurses::COLOR_BLACK);
+    pancurses::init_pair(3, pancurses::COLOR_BLUE, pancurses::COLOR_BLACK);
+    pancurses::init_pair(4, pancurses::COLOR_RED, pancurses::COLOR_BLACK);
+    pancurses::curs_set(0);
+    pancurses::noecho();
+
+    let result = run(&mut program, &window, &framebuffer);
+    if result.is_ok() {
+        window.mvprintw(size.1 as i32, 0, "Halted, press a key");
+        window.getch();
+    }
+    pancurses::endwin();
+
+    for output in program.drain_outputs() {
+        println!("{}", output);
+    }
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
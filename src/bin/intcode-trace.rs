@@ -0,0 +1,109 @@
+use std::env;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::process;
+use std::sync::{Arc, Mutex};
+use aoc::get_input;
+use aoc::intcode::Program;
+use aoc::intcode::trace::{self, BinaryWriter, JsonLinesWriter};
+
+const USAGE: &str = "\
+usage: intcode-trace <trace-file> [inputs...] < program
+       intcode-trace --diff <trace-a> <trace-b>
+
+Traces are written as JSON lines if the file name ends in .jsonl, and in
+the binary format otherwise.  Only binary traces can be diffed.  Inputs
+that aren't numbers are sent as ASCII lines.";
+
+fn run(program: &mut Program, inputs: &[String]) {
+    for input in inputs {
+        match input.parse() {
+            Ok(value) => program.set_input(value),
+            Err(_) => program.push_ascii(&format!("{}\n", input)),
+        }
+    }
+
+    if let Err(err) = program.run() {
+        eprintln!("{}", err);
+    }
+
+    for value in program.drain_outputs() {
+        println!("{}", value);
+    }
+}
+
+fn trace(filename: &str, inputs: &[String]) {
+    let memory: Vec<i64> = get_input()
+        .trim()
+        .split(',')
+        .map(|x| x.trim().parse().expect("NaN"))
+        .collect();
+    let mut program = Program::new(&memory);
+    let file = BufWriter::new(File::create(filename).expect("Could not create trace file"));
+
+    let result = if filename.ends_with(".jsonl") {
+        let tracer = Arc::new(Mutex::new(JsonLinesWriter::new(file)));
+        program.set_tracer(tracer.clone());
+        run(&mut program, inputs);
+        let result = tracer.lock().unwrap().flush();
+        result
+    } else {
+        let tracer = Arc::new(Mutex::new(BinaryWriter::new(file).unwrap()));
+        program.set_tracer(tracer.clone());
+        run(&mut program, inputs);
+        let result = tracer.lock().unwrap().flush();
+        result
+    };
+
+    if let Err(err) = result {
+        eprintln!("Could not write trace: {}", err);
+        process::exit(1);
+    }
+}
+
+fn load(filename: &str) -> Vec<trace::TraceEvent> {
+    let file = File::open(filename).expect("Could not open trace file");
+    match trace::read_binary(BufReader::new(file)) {
+        Ok(events) => events,
+        Err(err) => {
+            eprintln!("{}: {}", filename, err);
+            process::exit(1);
+        },
+    }
+}
+
+fn diff(a: &str, b: &str) {
+    let trace_a = load(a);
+    let trace_b = load(b);
+
+    match trace::first_divergence(&trace_a, &trace_b) {
+        Some(index) => {
+            println!("Traces diverge at event {}", index);
+            if index > 0 {
+                println!("  common: {}", trace_a[index - 1]);
+            }
+            match trace_a.get(index) {
+                Some(event) => println!("  {}: {}", a, event),
+                None => println!("  {}: <end of trace>", a),
+            }
+            match trace_b.get(index) {
+                Some(event) => println!("  {}: {}", b, event),
+                None => println!("  {}: <end of trace>", b),
+            }
+        },
+        None => println!("Traces are identical ({} events)", trace_a.len()),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    match args.get(1).map(String::as_str) {
+        Some("--diff") if args.len() == 4 => diff(&args[2], &args[3]),
+        Some(filename) if !filename.starts_with('-') => trace(filename, &args[2..]),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(1);
+        },
+    }
+}
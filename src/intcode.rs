@@ -1,29 +1,192 @@
-use std::collections::HashMap;
-
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use cell::Cell;
+use code::{Code, Op};
+use device::Devices;
+use history::{Change, Event, History};
+use isa::{Builtin, InstructionSet, Shape};
+use memory::Memory;
+use profile::Profile;
+use trace::{TraceEvent, Tracer};
+use watch::{Access, WatchEvent, Watchpoints};
+
+pub mod ascii;
+pub mod asm;
+pub mod cell;
+pub mod cfg;
+mod code;
+pub mod debugger;
+pub mod decompile;
+pub mod device;
+pub mod disasm;
+mod history;
+pub mod host;
+pub mod isa;
+pub mod memory;
+pub mod net;
+pub mod patch;
+pub mod profile;
+pub mod router;
+pub mod snapshot;
+pub mod symbolic;
+pub mod trace;
+pub mod watch;
+pub mod watchdog;
+
+const OPCODES: [i64; 10] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99];
+
+/// An intcode program, with memory cells of type `T`.
+///
+/// Everything works with any `Cell` type except symbolic execution
+/// (`Program::symbolic`) and binary traces (`trace::BinaryWriter`), which
+/// are only available for `i64` cells.
 #[derive(Clone)]
-pub struct Program {
+pub struct Program<T: Cell = i64> {
     ip: usize,
-    memory: HashMap<usize, i64>,
+    memory: Memory<T>,
+    code: Code,
+    /// `None` for the standard instruction set
+    isa: Option<Arc<InstructionSet<T>>>,
     halted: bool,
     jumped: bool,
-    inputs: Vec<i64>,
-    outputs: Vec<i64>,
+    checked: bool,
+    inputs: VecDeque<T>,
+    outputs: VecDeque<T>,
     relative_base: i64,
+    instruction_count: u64,
+    tracer: Option<Arc<Mutex<dyn Tracer<T>>>>,
+    /// The event for the instruction being traced, which `read_param` and
+    /// `write_param` fill in as it runs
+    event: Option<TraceEvent<T>>,
+    profile: Option<Box<Profile>>,
+    watchpoints: Watchpoints<T>,
+    devices: Devices<T>,
+    history: Option<History<T>>,
+}
+
+/// Why `Program::run_until_blocked` returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunState<T = i64> {
+    Halted,
+    NeedsInput,
+    Output(T),
+}
+
+/// An error raised while executing a program.  Every variant carries the
+/// instruction pointer and the raw instruction word that caused it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmError {
+    UnknownOpcode { ip: usize, instruction: i64 },
+    InvalidMode { ip: usize, instruction: i64, mode: i64 },
+    WriteToImmediate { ip: usize, instruction: i64 },
+    NegativeAddress { ip: usize, instruction: i64, address: i64 },
+    InputStarvation { ip: usize, instruction: i64 },
+    /// A `TRAP` instruction was executed, see `isa::Trap`.
+    Trap { ip: usize, instruction: i64 },
+    /// A host call wasn't registered or failed, see `host::HostCalls`.
+    HostCall { ip: usize, instruction: i64, message: String },
+    /// An add or multiply overflowed in checked mode, a relative address
+    /// overflowed, or a value that has to fit in an `i64` didn't.  The instruction is 0 if it's the
+    /// instruction word itself that doesn't fit.
+    Overflow { ip: usize, instruction: i64 },
+}
+
+impl VmError {
+    pub fn ip(&self) -> usize {
+        match *self {
+            VmError::UnknownOpcode { ip, .. } => ip,
+            VmError::InvalidMode { ip, .. } => ip,
+            VmError::WriteToImmediate { ip, .. } => ip,
+            VmError::NegativeAddress { ip, .. } => ip,
+            VmError::InputStarvation { ip, .. } => ip,
+            VmError::Trap { ip, .. } => ip,
+            VmError::HostCall { ip, .. } => ip,
+            VmError::Overflow { ip, .. } => ip,
+        }
+    }
+
+    pub fn instruction(&self) -> i64 {
+        match *self {
+            VmError::UnknownOpcode { instruction, .. } => instruction,
+            VmError::InvalidMode { instruction, .. } => instruction,
+            VmError::WriteToImmediate { instruction, .. } => instruction,
+            VmError::NegativeAddress { instruction, .. } => instruction,
+            VmError::InputStarvation { instruction, .. } => instruction,
+            VmError::Trap { instruction, .. } => instruction,
+            VmError::HostCall { instruction, .. } => instruction,
+            VmError::Overflow { instruction, .. } => instruction,
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmError::UnknownOpcode { .. } => write!(f, "unknown opcode")?,
+            VmError::InvalidMode { mode, .. } => {
+                write!(f, "invalid parameter mode {}", mode)?
+            },
+            VmError::WriteToImmediate { .. } => {
+                write!(f, "write to immediate mode parameter")?
+            },
+            VmError::NegativeAddress { address, .. } => {
+                write!(f, "negative address {}", address)?
+            },
+            VmError::InputStarvation { .. } => write!(f, "no input available")?,
+            VmError::Trap { .. } => write!(f, "trap")?,
+            VmError::HostCall { message, .. } => write!(f, "host call failed: {}", message)?,
+            VmError::Overflow { .. } => write!(f, "overflow")?,
+        }
+        write!(f, " at ip {} (instruction {})", self.ip(), self.instruction())
+    }
 }
 
-enum Param {
-    Position(usize),
+impl Error for VmError {}
+
+/// A decoded instruction parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Param {
+    Position(i64),
     Immediate(i64),
     Relative(i64),
 }
 
 impl Param {
-    fn new(value: i64, mode_code: i64) -> Param {
+    fn new(value: i64, mode_code: i64) -> Option<Param> {
         match mode_code {
-            0 => Param::Position(value as usize),
-            1 => Param::Immediate(value),
-            2 => Param::Relative(value),
-            _ => panic!("Unknown parameter mode {}", mode_code),
+            0 => Some(Param::Position(value)),
+            1 => Some(Param::Immediate(value)),
+            2 => Some(Param::Relative(value)),
+            _ => None,
+        }
+    }
+
+    fn mode_code(&self) -> i64 {
+        match self {
+            Param::Position(_) => 0,
+            Param::Immediate(_) => 1,
+            Param::Relative(_) => 2,
+        }
+    }
+
+    fn value(&self) -> i64 {
+        match *self {
+            Param::Position(value) => value,
+            Param::Immediate(value) => value,
+            Param::Relative(value) => value,
+        }
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Param::Position(addr) => write!(f, "[{}]", addr),
+            Param::Immediate(value) => write!(f, "#{}", value),
+            Param::Relative(offset) if *offset < 0 => write!(f, "rb{}", offset),
+            Param::Relative(offset) => write!(f, "rb+{}", offset),
         }
     }
 }
@@ -34,238 +197,633 @@ struct Instruction {
 }
 
 impl Instruction {
-    pub fn new(opcode: i64) -> Instruction {
+    /// Decodes the instruction at `ip`, using `read` to fetch memory words.
+    fn decode<F: Fn(usize) -> i64>(ip: usize, read: F) -> Result<Instruction, VmError> {
+        Op::decode(ip, Shape::standard, |addr| Some(read(addr))).map(|op| op.instruction())
+    }
+
+    fn new(opcode: i64) -> Instruction {
         Instruction {
             opcode,
             params: vec![],
         }
     }
 
-    pub fn len(&self) -> usize {
-        match self.opcode {
-            1 => 4,
-            2 => 4,
-            3 => 2,
-            4 => 2,
-            5 => 3,
-            6 => 3,
-            7 => 4,
-            8 => 4,
-            9 => 2,
-            99 => 1,
-            _ => panic!("Unknown opcode: {}", self.opcode),
-        }
+    fn len(&self) -> Option<usize> {
+        Builtin::standard(self.opcode).map(|builtin| builtin.param_count() + 1)
+    }
+
+    fn mnemonic(&self) -> &'static str {
+        Builtin::standard(self.opcode).map_or("???", |builtin| builtin.mnemonic())
     }
 
-    pub fn add_param(&mut self, param: Param) {
-        self.params.push(param);
+    /// Whether the last parameter is a write target rather than a value.
+    fn writes(&self) -> bool {
+        Builtin::standard(self.opcode).is_some_and(|builtin| builtin.writes())
     }
 }
 
-impl Program {
-    pub fn new(initial_memory: &Vec<i64>) -> Program {
-        let mut memory = HashMap::new();
-        for (addr, &value) in initial_memory.iter().enumerate() {
-            memory.insert(addr, value);
+impl Instruction {
+    /// Formats the instruction with the given mnemonic, for instructions
+    /// outside the standard set.
+    fn text(&self, mnemonic: &str, writes: bool) -> String {
+        let (reads, target) = match self.params.split_last() {
+            Some((target, reads)) if writes => (reads, Some(target)),
+            _ => (&self.params[..], None),
+        };
+
+        let mut text = String::from(mnemonic);
+        for (i, param) in reads.iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            text += &format!("{}{}", separator, param);
         }
+        if let Some(target) = target {
+            text += &format!(" -> {}", target);
+        }
+        text
+    }
+}
 
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text(self.mnemonic(), self.writes()))
+    }
+}
+
+impl<T: Cell> Program<T> {
+    pub fn new(initial_memory: &[T]) -> Program<T> {
         Program {
             ip: 0,
-            memory,
+            memory: Memory::new(initial_memory),
+            code: Code::new(initial_memory, Shape::standard),
+            isa: None,
             halted: false,
             jumped: false,
-            inputs: vec![],
-            outputs: vec![],
+            checked: false,
+            inputs: VecDeque::new(),
+            outputs: VecDeque::new(),
             relative_base: 0,
+            instruction_count: 0,
+            tracer: None,
+            event: None,
+            profile: None,
+            watchpoints: Watchpoints::default(),
+            devices: Devices::default(),
+            history: None,
         }
     }
 
-    pub fn reset(&mut self, initial_memory: &Vec<i64>) {
-        let mut memory = HashMap::new();
-        for (addr, &value) in initial_memory.iter().enumerate() {
-            memory.insert(addr, value);
-        }
-
+    pub fn reset(&mut self, initial_memory: &[T]) {
         self.ip = 0;
-        self.memory = memory;
+        self.memory = Memory::new(initial_memory);
+        self.code = match &self.isa {
+            Some(isa) => Code::new(initial_memory, |opcode| isa.shape(opcode)),
+            None => Code::new(initial_memory, Shape::standard),
+        };
         self.halted = false;
         self.jumped = false;
         self.inputs.clear();
         self.outputs.clear();
         self.relative_base = 0;
+        self.instruction_count = 0;
+        if self.history.is_some() {
+            self.start_recording();
+        }
     }
 
     pub fn is_running(&self) -> bool {
         !self.halted
     }
 
-    pub fn run(&mut self) {
+    /// In checked mode, an add or multiply that overflows the cell type,
+    /// or a relative base adjustment that overflows an `i64`, fails with
+    /// `VmError::Overflow` instead of wrapping.  Relative addresses that
+    /// overflow are always an error.
+    pub fn set_checked(&mut self, checked: bool) {
+        self.checked = checked;
+    }
+
+    pub fn is_checked(&self) -> bool {
+        self.checked
+    }
+
+    /// Replaces the standard instruction set, see `InstructionSet`.
+    pub fn set_instruction_set(&mut self, isa: InstructionSet<T>) {
+        let image: Vec<T> = (0..self.code.len()).map(|addr| self.read(addr)).collect();
+        self.code = Code::new(&image, |opcode| isa.shape(opcode));
+        self.isa = Some(Arc::new(isa));
+    }
+
+    fn shape(&self, opcode: i64) -> Option<Shape> {
+        match &self.isa {
+            Some(isa) => isa.shape(opcode),
+            None => Shape::standard(opcode),
+        }
+    }
+
+    pub fn run(&mut self) -> Result<(), VmError> {
         while !self.halted {
-            self.step();
+            self.step()?;
         }
+
+        Ok(())
     }
 
-    pub fn pause_on_output(&mut self) -> Option<i64> {
-        while self.outputs.len() == 0 && !self.halted {
-            self.step();
+    pub fn pause_on_output(&mut self) -> Result<Option<T>, VmError> {
+        while self.outputs.is_empty() && !self.halted {
+            self.step()?;
         }
 
-        self.pop_output()
+        Ok(self.pop_output())
     }
 
+    /// Runs until the program halts, needs input that isn't queued, or
+    /// produces an output, which is removed from the output queue.  Any
+    /// output queued before the call is returned first.
+    pub fn run_until_blocked(&mut self) -> Result<RunState<T>, VmError> {
+        loop {
+            if let Some(output) = self.pop_output() {
+                return Ok(RunState::Output(output));
+            }
+            if self.halted {
+                return Ok(RunState::Halted);
+            }
+            if self.needs_input() {
+                return Ok(RunState::NeedsInput);
+            }
+            self.step()?;
+        }
+    }
+
+    /// Whether the next instruction is an input with nothing queued.  Only
+    /// the opcode is looked at, so this never fails on garbage.
     pub fn needs_input(&self) -> bool {
-        let instruction = self.get_instruction();
-        instruction.opcode == 3 && self.inputs.is_empty()
+        let opcode = self.read(self.ip).to_i64().map(|word| word % 100);
+        !self.halted
+            && self.inputs.is_empty()
+            && opcode.and_then(|opcode| self.shape(opcode)?.builtin) == Some(Builtin::Input)
     }
 
     pub fn has_output(&self) -> bool {
-        self.outputs.len() > 0
+        !self.outputs.is_empty()
     }
 
-    pub fn read(&self, addr: usize) -> i64 {
-        match self.memory.get(&addr) {
-            Some(value) => *value,
-            None => 0,
+    pub fn read(&self, addr: usize) -> T {
+        self.memory.read(addr)
+    }
+
+    pub fn write(&mut self, addr: usize, value: T) {
+        if self.history.is_some() {
+            self.record(Event::Poke { addr, old: self.read(addr) });
         }
+        self.store(addr, value);
     }
 
-    pub fn set_input(&mut self, input: i64) {
-        self.inputs.insert(0, input);
+    fn store(&mut self, addr: usize, value: T) {
+        self.memory.write(addr, value);
+        self.code.invalidate(addr);
     }
 
-    pub fn pop_output(&mut self) -> Option<i64> {
-        self.outputs.pop()
+    pub fn ip(&self) -> usize {
+        self.ip
     }
 
-    fn raw_param(&self, offset: usize) -> i64 {
-        self.read(self.ip + offset)
+    /// Moves the instruction pointer, for getting past a trap or skipping
+    /// code.  It's not undone by stepping back.
+    pub fn set_ip(&mut self, ip: usize) {
+        self.ip = ip;
     }
 
-    fn write(&mut self, addr_param: &Param, value: i64) {
-        let addr = self.get_write_address(&addr_param);
-        self.memory.insert(addr, value);
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
     }
 
-    fn get_write_address(&self, param: &Param) -> usize {
-        match param {
-            Param::Position(addr) => *addr,
-            Param::Relative(offset) => (self.relative_base + offset) as usize,
-            _ => panic!("Unsupported parameter mode for write"),
+    /// Returns the number of instructions executed so far.
+    pub fn instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
+    /// Attaches a tracer that gets notified of every executed instruction.
+    /// Clones of the program share the same tracer.
+    pub fn set_tracer(&mut self, tracer: Arc<Mutex<dyn Tracer<T>>>) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn clear_tracer(&mut self) {
+        self.tracer = None;
+    }
+
+    /// Starts counting executed instructions, see `Profile`.  Any profile
+    /// collected so far is thrown away.
+    pub fn start_profiling(&mut self) {
+        self.profile = Some(Box::new(Profile::new()));
+    }
+
+    /// Stops profiling and returns what was collected.
+    pub fn stop_profiling(&mut self) -> Option<Profile> {
+        self.profile.take().map(|profile| *profile)
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_deref()
+    }
+
+    pub fn set_input(&mut self, input: T) {
+        self.inputs.push_back(input);
+        self.record(Event::InputQueued);
+    }
+
+    pub fn push_inputs<I: IntoIterator<Item = T>>(&mut self, inputs: I) {
+        for input in inputs {
+            self.set_input(input);
+        }
+    }
+
+    /// Queues each character of `text` as an input.  No newline is added.
+    pub fn push_ascii(&mut self, text: &str) {
+        self.push_inputs(text.chars().map(|ch| T::from_i64(ch as i64)));
+    }
+
+    /// Returns the queued inputs, in the order they will be consumed.
+    pub fn pending_inputs(&self) -> Vec<T> {
+        self.inputs.iter().cloned().collect()
+    }
+
+    pub fn clear_inputs(&mut self) {
+        if self.history.is_some() {
+            self.record(Event::InputsCleared(self.pending_inputs()));
+        }
+        self.inputs.clear();
+    }
+
+    /// Removes and returns the oldest output.
+    pub fn pop_output(&mut self) -> Option<T> {
+        let output = self.outputs.pop_front()?;
+        if self.history.is_some() {
+            self.record(Event::OutputTaken(output.clone()));
+        }
+        Some(output)
+    }
+
+    /// Removes and returns all outputs, oldest first.
+    pub fn drain_outputs(&mut self) -> Vec<T> {
+        let mut outputs = vec![];
+        while let Some(output) = self.pop_output() {
+            outputs.push(output);
         }
+        outputs
     }
 
-    fn read_param(&self, param: &Param) -> i64 {
+    /// Runs until `count` outputs are available or the program halts, and
+    /// returns up to `count` of the oldest outputs.
+    pub fn run_until_output_count(&mut self, count: usize) -> Result<Vec<T>, VmError> {
+        while self.outputs.len() < count && !self.halted {
+            self.step()?;
+        }
+
+        let mut outputs = vec![];
+        while outputs.len() < count {
+            match self.pop_output() {
+                Some(output) => outputs.push(output),
+                None => break,
+            }
+        }
+        Ok(outputs)
+    }
+
+    /// Writes to the address a parameter points at, like an instruction
+    /// would.
+    pub fn write_param(&mut self, addr_param: &Param, value: T) -> Result<(), VmError> {
+        let addr = self.get_write_address(addr_param)?;
+        if !self.watchpoints.is_empty() {
+            self.watch(Access::Write, addr, self.read(addr), value.clone());
+        }
+        if let Some(event) = &mut self.event {
+            event.write = Some((addr, value.clone()));
+        }
+        match self.devices.get(addr) {
+            Some((offset, device)) => device.lock().unwrap().write(offset, value),
+            None => self.store(addr, value),
+        }
+        Ok(())
+    }
+
+    fn get_write_address(&self, param: &Param) -> Result<usize, VmError> {
         match param {
-            Param::Position(addr) => self.read(*addr),
-            Param::Immediate(value) => *value,
-            Param::Relative(offset) => {
-                let addr = (self.relative_base + offset) as usize;
-                self.read(addr)
+            Param::Position(addr) => self.address(*addr),
+            Param::Relative(offset) => self.relative_address(*offset),
+            Param::Immediate(_) => Err(VmError::WriteToImmediate {
+                ip: self.ip,
+                instruction: self.instruction_word(),
+            }),
+        }
+    }
+
+    /// Reads a parameter's value, like an instruction would.
+    pub fn read_param(&mut self, param: &Param) -> Result<T, VmError> {
+        let addr = match param {
+            Param::Position(addr) => Some(self.address(*addr)?),
+            Param::Immediate(_) => None,
+            Param::Relative(offset) => Some(self.relative_address(*offset)?),
+        };
+
+        let value = match addr {
+            Some(addr) => {
+                let value = match self.devices.get(addr) {
+                    Some((offset, device)) => device.lock().unwrap().read(offset),
+                    None => self.read(addr),
+                };
+                if !self.watchpoints.is_empty() {
+                    self.watch(Access::Read, addr, value.clone(), value.clone());
+                }
+                value
             },
+            None => T::from_i64(param.value()),
+        };
+        if let Some(event) = &mut self.event {
+            event.reads.push(value.clone());
         }
+        Ok(value)
     }
 
-    fn get_instruction(&self) -> Instruction {
-        let raw_opcode = self.read(self.ip);
-        let opcode = raw_opcode % 100;
-        let mut instruction = Instruction::new(opcode);
-        let param_modifiers = raw_opcode - opcode;
+    /// Reads a parameter that has to fit in an `i64`, like a jump target.
+    fn read_param_i64(&mut self, param: &Param) -> Result<i64, VmError> {
+        match self.read_param(param)?.to_i64() {
+            Some(value) => Ok(value),
+            None => Err(self.overflow()),
+        }
+    }
+
+    fn watch(&self, access: Access, addr: usize, old: T, new: T) {
+        self.watchpoints.notify(WatchEvent { access, addr, ip: self.ip, old, new });
+    }
 
-        for i in 1..instruction.len() {
-            let value = self.raw_param(i);
-            let mode_code = (param_modifiers / 10i64.pow(i as u32 + 1)) % 10;
-            let param = Param::new(value, mode_code);
-            instruction.add_param(param);
+    /// The address `offset` from the relative base.  A sum that doesn't
+    /// fit in an `i64` isn't an address at all, so it's an error even when
+    /// arithmetic is allowed to wrap.
+    fn relative_address(&self, offset: i64) -> Result<usize, VmError> {
+        match self.relative_base.checked_add(offset) {
+            Some(addr) => self.address(addr),
+            None => Err(self.overflow()),
         }
+    }
 
-        instruction
+    fn address(&self, addr: i64) -> Result<usize, VmError> {
+        if addr < 0 {
+            Err(VmError::NegativeAddress {
+                ip: self.ip,
+                instruction: self.instruction_word(),
+                address: addr,
+            })
+        } else {
+            Ok(addr as usize)
+        }
+    }
+
+    /// The word at the instruction pointer, for error reports.
+    fn instruction_word(&self) -> i64 {
+        self.read(self.ip).to_i64().unwrap_or(0)
+    }
+
+    fn overflow(&self) -> VmError {
+        VmError::Overflow { ip: self.ip, instruction: self.instruction_word() }
     }
 
-    pub fn step(&mut self) {
-        let instruction = self.get_instruction();
-        let opcode = instruction.opcode;
-        match opcode {
-            1 => self.add(&instruction.params),
-            2 => self.mult(&instruction.params),
-            3 => self.input(&instruction.params),
-            4 => self.output(&instruction.params),
-            5 => self.jump_if_true(&instruction.params),
-            6 => self.jump_if_false(&instruction.params),
-            7 => self.lt(&instruction.params),
-            8 => self.eq(&instruction.params),
-            9 => self.add_relbase(&instruction.params),
-            99 => self.halt(),
-            _ => panic!("Unknown opcode: {}", opcode),
+    fn get_instruction(&self) -> Result<Op, VmError> {
+        match self.code.get(self.ip) {
+            Some(op) => Ok(op),
+            None => Op::decode(
+                self.ip,
+                |opcode| self.shape(opcode),
+                |addr| self.read(addr).to_i64(),
+            ),
         }
-        if !self.jumped {
-            self.ip += instruction.len();
+    }
+
+    /// Starts the trace event for `op`.  `read_param` and `write_param`
+    /// add the values it reads and writes as it executes.
+    fn trace_event(&self, op: &Op) -> TraceEvent<T> {
+        TraceEvent {
+            step: self.instruction_count,
+            ip: self.ip,
+            instruction: self.instruction_word(),
+            params: op.params().to_vec(),
+            reads: vec![],
+            write: None,
+            relative_base: self.relative_base,
+        }
+    }
+
+    /// Describes what executing `op` is going to change, for recording.
+    fn change(&self, op: &Op) -> Result<Change<T>, VmError> {
+        let params = op.params();
+        let change = match op.builtin {
+            Some(Builtin::Add) | Some(Builtin::Mul) | Some(Builtin::LessThan)
+            | Some(Builtin::Equals) => {
+                let addr = self.get_write_address(&params[2])?;
+                Change::Write { addr, old: self.read(addr) }
+            },
+            Some(Builtin::Input) => {
+                let addr = self.get_write_address(&params[0])?;
+                match self.inputs.front() {
+                    Some(value) => {
+                        Change::Input { addr, old: self.read(addr), value: value.clone() }
+                    },
+                    None => Change::None,
+                }
+            },
+            Some(Builtin::Output) => Change::Output,
+            Some(Builtin::AdjustBase) => Change::RelativeBase(self.relative_base),
+            Some(Builtin::Halt) => Change::Halt,
+            Some(Builtin::JumpIfTrue) | Some(Builtin::JumpIfFalse) => Change::None,
+            None if op.writes() => {
+                let addr = self.get_write_address(params.last().unwrap())?;
+                Change::Write { addr, old: self.read(addr) }
+            },
+            None => Change::None,
+        };
+
+        Ok(change)
+    }
+
+    fn profile_step(&mut self, ip: usize, op: &Op, jumped: bool) {
+        let pushed = match op.params().last() {
+            Some(Param::Relative(offset)) if op.writes() => self
+                .relative_base
+                .checked_add(*offset)
+                .filter(|&addr| addr >= 0)
+                .and_then(|addr| self.read(addr as usize).to_i64()),
+            _ => None,
+        };
+
+        let next_ip = self.ip;
+        if let Some(profile) = self.profile.as_mut() {
+            profile.record(ip, op.opcode, op.len(), next_ip, jumped, pushed);
+        }
+    }
+
+    pub fn step(&mut self) -> Result<(), VmError> {
+        let op = self.get_instruction()?;
+        self.event = self.tracer.as_ref().map(|_| self.trace_event(&op));
+
+        let ip = self.ip;
+        let change = match self.history {
+            Some(_) => Some(self.change(&op)?),
+            None => None,
+        };
+
+        let params = op.params();
+        match op.builtin {
+            Some(Builtin::Add) => self.add(params)?,
+            Some(Builtin::Mul) => self.mult(params)?,
+            Some(Builtin::Input) => self.input(params)?,
+            Some(Builtin::Output) => self.output(params)?,
+            Some(Builtin::JumpIfTrue) => self.jump_if_true(params)?,
+            Some(Builtin::JumpIfFalse) => self.jump_if_false(params)?,
+            Some(Builtin::LessThan) => self.lt(params)?,
+            Some(Builtin::Equals) => self.eq(params)?,
+            Some(Builtin::AdjustBase) => self.add_relbase(params)?,
+            Some(Builtin::Halt) => self.halt(),
+            None => {
+                let isa = self.isa.as_ref().expect("extensions need an instruction set");
+                let extension = isa.extension(op.opcode).expect("opcode validated by Op::decode");
+                extension.execute(self, params)?;
+            },
+        }
+        let jumped = self.jumped;
+        if !jumped {
+            self.ip += params.len() + 1;
         }
         self.jumped = false;
+        self.instruction_count += 1;
+
+        if self.profile.is_some() {
+            self.profile_step(ip, &op, jumped);
+        }
+        if let Some(change) = change {
+            self.record(Event::Step { ip, change });
+        }
+
+        if let (Some(tracer), Some(event)) = (&self.tracer, self.event.take()) {
+            tracer.lock().unwrap().trace(&event);
+        }
+
+        Ok(())
     }
 
-    fn add(&mut self, params: &Vec<Param>) {
-        let val0 = self.read_param(&params[0]);
-        let val1 = self.read_param(&params[1]);
-        self.write(&params[2], val0 + val1);
+    fn add(&mut self, params: &[Param]) -> Result<(), VmError> {
+        let val0 = self.read_param(&params[0])?;
+        let val1 = self.read_param(&params[1])?;
+        let sum = if self.checked {
+            val0.add_checked(&val1).ok_or_else(|| self.overflow())?
+        } else {
+            val0.wrapping_add(&val1)
+        };
+        self.write_param(&params[2], sum)
     }
 
-    fn mult(&mut self, params: &Vec<Param>) {
-        let val0 = self.read_param(&params[0]);
-        let val1 = self.read_param(&params[1]);
-        self.write(&params[2], val0 * val1);
+    fn mult(&mut self, params: &[Param]) -> Result<(), VmError> {
+        let val0 = self.read_param(&params[0])?;
+        let val1 = self.read_param(&params[1])?;
+        let product = if self.checked {
+            val0.mul_checked(&val1).ok_or_else(|| self.overflow())?
+        } else {
+            val0.wrapping_mul(&val1)
+        };
+        self.write_param(&params[2], product)
     }
 
-    fn input(&mut self, params: &Vec<Param>) {
-        match self.inputs.pop() {
-            Some(input) => self.write(&params[0], input),
-            None => panic!("No input available"),
+    fn input(&mut self, params: &[Param]) -> Result<(), VmError> {
+        // Resolve the target first so a bad address doesn't eat the input
+        self.get_write_address(&params[0])?;
+
+        match self.inputs.pop_front() {
+            Some(input) => self.write_param(&params[0], input),
+            None => Err(VmError::InputStarvation {
+                ip: self.ip,
+                instruction: self.instruction_word(),
+            }),
         }
     }
 
-    fn output(&mut self, params: &Vec<Param>) {
-        let val = self.read_param(&params[0]);
-        self.outputs.push(val);
+    fn output(&mut self, params: &[Param]) -> Result<(), VmError> {
+        let val = self.read_param(&params[0])?;
+        self.push_output(val);
+        Ok(())
+    }
+
+    /// Queues an output, like an output instruction would.
+    pub fn push_output(&mut self, value: T) {
+        self.outputs.push_back(value);
     }
 
-    fn jump_if_true(&mut self, params: &Vec<Param>) {
-        let val = self.read_param(&params[0]);
-        if val != 0 {
-            self.jump(&params[1]);
+    fn jump_if_true(&mut self, params: &[Param]) -> Result<(), VmError> {
+        let val = self.read_param(&params[0])?;
+        if !val.is_zero() {
+            self.jump(&params[1])?;
         }
+        Ok(())
     }
 
-    fn jump_if_false(&mut self, params: &Vec<Param>) {
-        let val = self.read_param(&params[0]);
-        if val == 0 {
-            self.jump(&params[1]);
+    fn jump_if_false(&mut self, params: &[Param]) -> Result<(), VmError> {
+        let val = self.read_param(&params[0])?;
+        if val.is_zero() {
+            self.jump(&params[1])?;
         }
+        Ok(())
     }
 
-    fn jump(&mut self, param: &Param) {
-        self.ip = self.read_param(param) as usize;
+    /// Jumps to the address a parameter holds, like a jump instruction
+    /// would.
+    pub fn jump(&mut self, param: &Param) -> Result<(), VmError> {
+        let target = self.read_param_i64(param)?;
+        self.ip = self.address(target)?;
         self.jumped = true;
+        Ok(())
     }
 
-    fn lt(&mut self, params: &Vec<Param>) {
-        let val0 = self.read_param(&params[0]);
-        let val1 = self.read_param(&params[1]);
+    fn lt(&mut self, params: &[Param]) -> Result<(), VmError> {
+        let val0 = self.read_param(&params[0])?;
+        let val1 = self.read_param(&params[1])?;
 
-        self.write(&params[2], (val0 < val1) as i64);
+        self.write_param(&params[2], T::from_i64((val0 < val1) as i64))
     }
 
-    fn eq(&mut self, params: &Vec<Param>) {
-        let val0 = self.read_param(&params[0]);
-        let val1 = self.read_param(&params[1]);
+    fn eq(&mut self, params: &[Param]) -> Result<(), VmError> {
+        let val0 = self.read_param(&params[0])?;
+        let val1 = self.read_param(&params[1])?;
 
-        self.write(&params[2], (val0 == val1) as i64);
+        self.write_param(&params[2], T::from_i64((val0 == val1) as i64))
     }
 
-    fn add_relbase(&mut self, params: &Vec<Param>) {
-        let offset = self.read_param(&params[0]);
-        self.relative_base += offset;
+    fn add_relbase(&mut self, params: &[Param]) -> Result<(), VmError> {
+        let offset = self.read_param_i64(&params[0])?;
+        self.relative_base = match self.relative_base.checked_add(offset) {
+            Some(base) => base,
+            None if self.checked => return Err(self.overflow()),
+            None => self.relative_base.wrapping_add(offset),
+        };
+        Ok(())
     }
 
-    fn halt(&mut self) {
+    pub fn halt(&mut self) {
         self.halted = true;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overflowing_relative_address_is_an_error() {
+        // ARB #max; OUT rb+1; HLT
+        let mut program = Program::new(&[109, i64::MAX, 204, 1, 99]);
+        assert_eq!(program.run(), Err(VmError::Overflow { ip: 2, instruction: 204 }));
+
+        // ARB #max; IN -> rb+1
+        let mut program = Program::new(&[109, i64::MAX, 203, 1]);
+        program.set_input(1);
+        assert_eq!(program.run(), Err(VmError::Overflow { ip: 2, instruction: 203 }));
+    }
+}
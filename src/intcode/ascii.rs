@@ -0,0 +1,66 @@
+use super::{Program, RunState, VmError};
+
+/// Output collected by `AsciiProgram::read_until_prompt`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Response {
+    /// All output values up to 255, as text
+    pub text: String,
+    /// Output values too large to be characters, which is how ASCII
+    /// programs usually report their answer
+    pub values: Vec<i64>,
+}
+
+/// Wraps a program that talks ASCII: input is sent as lines of text and
+/// output is read back as text.
+#[derive(Clone)]
+pub struct AsciiProgram {
+    program: Program,
+}
+
+impl AsciiProgram {
+    pub fn new(memory: &[i64]) -> AsciiProgram {
+        AsciiProgram::from_program(Program::new(memory))
+    }
+
+    pub fn from_program(program: Program) -> AsciiProgram {
+        AsciiProgram { program }
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn program_mut(&mut self) -> &mut Program {
+        &mut self.program
+    }
+
+    pub fn into_program(self) -> Program {
+        self.program
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.program.is_running()
+    }
+
+    /// Queues a line of input, adding the trailing newline.
+    pub fn send_line(&mut self, line: &str) {
+        self.program.push_ascii(line);
+        self.program.set_input(10);
+    }
+
+    /// Runs until the program wants more input than is queued, or halts,
+    /// and returns everything it printed along the way.
+    pub fn read_until_prompt(&mut self) -> Result<Response, VmError> {
+        let mut response = Response::default();
+
+        loop {
+            match self.program.run_until_blocked()? {
+                RunState::Output(value) if (0..=255).contains(&value) => {
+                    response.text.push((value as u8) as char);
+                },
+                RunState::Output(value) => response.values.push(value),
+                RunState::NeedsInput | RunState::Halted => return Ok(response),
+            }
+        }
+    }
+}
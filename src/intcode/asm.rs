@@ -0,0 +1,519 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use super::{Instruction, OPCODES};
+
+const MAX_MACRO_DEPTH: usize = 32;
+
+/// An assembly error, with the (1-based) source line it occurred on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl AsmError {
+    fn new(line: usize, message: String) -> AsmError {
+        AsmError { line, message }
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+enum Expr {
+    Number(i64),
+    Label(String, i64),
+}
+
+struct Operand {
+    mode: i64,
+    expr: Expr,
+}
+
+enum Item {
+    Instruction(i64, Vec<Operand>),
+    Data(Vec<Expr>),
+}
+
+struct Statement {
+    line: usize,
+    labels: Vec<String>,
+    address: Option<usize>,
+    item: Option<Item>,
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<String>,
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(ch) if ch.is_ascii_alphabetic() || ch == '_' || ch == '.' => (),
+        _ => return false,
+    }
+    chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '.')
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find(';') {
+        Some(pos) => &line[..pos],
+        None => line,
+    }
+}
+
+fn split_args(args: &str) -> Vec<String> {
+    if args.trim().is_empty() {
+        vec![]
+    } else {
+        args.split(',').map(|arg| String::from(arg.trim())).collect()
+    }
+}
+
+fn split_first_word(s: &str) -> (&str, &str) {
+    let s = s.trim();
+    match s.find(char::is_whitespace) {
+        Some(pos) => (&s[..pos], s[pos..].trim()),
+        None => (s, ""),
+    }
+}
+
+/// Strips comments, collects macro definitions and expands macro
+/// invocations, keeping track of the source line each line came from.
+fn preprocess(source: &str) -> Result<Vec<(usize, String)>, AsmError> {
+    let mut macros = HashMap::new();
+    let mut lines = vec![];
+    let mut current_macro: Option<(usize, String, Macro)> = None;
+
+    for (index, raw_line) in source.lines().enumerate() {
+        let line_no = index + 1;
+        let line = strip_comment(raw_line).trim();
+        let (first, rest) = split_first_word(line);
+
+        if let Some((start, name, mut definition)) = current_macro.take() {
+            if first.eq_ignore_ascii_case(".endm") {
+                macros.insert(name, definition);
+            } else if first.eq_ignore_ascii_case(".macro") {
+                return Err(AsmError::new(line_no, String::from("nested .macro definition")));
+            } else {
+                definition.body.push(String::from(line));
+                current_macro = Some((start, name, definition));
+            }
+            continue;
+        }
+
+        if first.eq_ignore_ascii_case(".macro") {
+            let (name, params) = split_first_word(rest);
+            if !is_identifier(name) {
+                return Err(AsmError::new(line_no, format!("invalid macro name '{}'", name)));
+            }
+            let definition = Macro { params: split_args(params), body: vec![] };
+            current_macro = Some((line_no, String::from(name), definition));
+        } else if first.eq_ignore_ascii_case(".endm") {
+            return Err(AsmError::new(line_no, String::from(".endm without .macro")));
+        } else if !line.is_empty() {
+            lines.push((line_no, String::from(line)));
+        }
+    }
+
+    if let Some((start, name, _)) = current_macro {
+        return Err(AsmError::new(start, format!("macro '{}' is never closed", name)));
+    }
+
+    let mut expanded = vec![];
+    let mut invocations = 0;
+    for (line_no, line) in lines {
+        expand(&macros, line_no, &line, &mut expanded, &mut invocations, 0)?;
+    }
+
+    Ok(expanded)
+}
+
+fn expand(
+    macros: &HashMap<String, Macro>,
+    line_no: usize,
+    line: &str,
+    output: &mut Vec<(usize, String)>,
+    invocations: &mut usize,
+    depth: usize,
+) -> Result<(), AsmError> {
+    let (labels, body) = split_labels(line);
+    let (first, rest) = split_first_word(body);
+
+    let definition = match macros.get(first) {
+        Some(definition) => definition,
+        None => {
+            output.push((line_no, String::from(line)));
+            return Ok(());
+        },
+    };
+
+    if depth >= MAX_MACRO_DEPTH {
+        return Err(AsmError::new(line_no, format!("macro '{}' expands too deeply", first)));
+    }
+
+    let args = split_args(rest);
+    if args.len() != definition.params.len() {
+        return Err(AsmError::new(line_no, format!(
+            "macro '{}' takes {} arguments, got {}",
+            first,
+            definition.params.len(),
+            args.len(),
+        )));
+    }
+
+    *invocations += 1;
+    let unique = invocations.to_string();
+
+    if !labels.is_empty() {
+        output.push((line_no, String::from(labels)));
+    }
+
+    for body_line in &definition.body {
+        let text = substitute(body_line, &definition.params, &args, &unique);
+        expand(macros, line_no, &text, output, invocations, depth + 1)?;
+    }
+
+    Ok(())
+}
+
+/// Replaces `\@` with `unique` and `\name` with the argument for `name`.
+/// Names are matched whole, so `\ab` is never `\a` followed by `b`.
+fn substitute(line: &str, params: &[String], args: &[String], unique: &str) -> String {
+    let mut text = String::new();
+    let mut rest = line;
+
+    while let Some(pos) = rest.find('\\') {
+        text += &rest[..pos];
+        rest = &rest[pos + 1..];
+
+        if let Some(after) = rest.strip_prefix('@') {
+            text += unique;
+            rest = after;
+            continue;
+        }
+
+        let end = rest
+            .find(|ch: char| !ch.is_ascii_alphanumeric() && ch != '_' && ch != '.')
+            .unwrap_or(rest.len());
+        match params.iter().position(|param| *param == rest[..end]) {
+            Some(i) => text += &args[i],
+            None => {
+                text.push('\\');
+                text += &rest[..end];
+            },
+        }
+        rest = &rest[end..];
+    }
+
+    text + rest
+}
+
+/// Splits leading `label:` definitions from the rest of a line.
+fn split_labels(line: &str) -> (&str, &str) {
+    let mut end = 0;
+    let mut rest = line;
+
+    while let Some(pos) = rest.find(':') {
+        let name = rest[..pos].trim();
+        if !is_identifier(name) && name.parse::<usize>().is_err() {
+            break;
+        }
+        end += pos + 1;
+        rest = &rest[pos + 1..];
+    }
+
+    (line[..end].trim(), rest.trim())
+}
+
+fn parse_expr(line: usize, s: &str) -> Result<Expr, AsmError> {
+    let s = s.trim();
+    if let Ok(value) = s.parse() {
+        return Ok(Expr::Number(value));
+    }
+
+    let (name, offset) = match s.rfind(['+', '-']) {
+        Some(pos) if pos > 0 => {
+            let offset = s[pos..].replace(' ', "").parse().map_err(|_| {
+                AsmError::new(line, format!("invalid offset in '{}'", s))
+            })?;
+            (s[..pos].trim(), offset)
+        },
+        _ => (s, 0),
+    };
+
+    if is_identifier(name) {
+        Ok(Expr::Label(String::from(name), offset))
+    } else {
+        Err(AsmError::new(line, format!("invalid expression '{}'", s)))
+    }
+}
+
+fn parse_operand(line: usize, s: &str) -> Result<Operand, AsmError> {
+    let s = s.trim();
+    let lower = s.to_ascii_lowercase();
+
+    if let Some(value) = s.strip_prefix('#') {
+        Ok(Operand { mode: 1, expr: parse_expr(line, value)? })
+    } else if s.starts_with('[') && s.ends_with(']') {
+        Ok(Operand { mode: 0, expr: parse_expr(line, &s[1..s.len() - 1])? })
+    } else if lower.starts_with("rb+") {
+        Ok(Operand { mode: 2, expr: parse_expr(line, &s[3..])? })
+    } else if lower.starts_with("rb-") {
+        Ok(Operand { mode: 2, expr: parse_expr(line, &s[2..])? })
+    } else {
+        Err(AsmError::new(line, format!("operand '{}' has no addressing mode", s)))
+    }
+}
+
+fn parse_instruction(line: usize, mnemonic: &str, args: &str) -> Result<Item, AsmError> {
+    let opcode = match OPCODES
+        .iter()
+        .find(|&&op| Instruction::new(op).mnemonic().eq_ignore_ascii_case(mnemonic))
+    {
+        Some(&opcode) => opcode,
+        None => return Err(AsmError::new(line, format!("unknown mnemonic '{}'", mnemonic))),
+    };
+    let instruction = Instruction::new(opcode);
+
+    let (reads, target) = match args.find("->") {
+        Some(pos) => (&args[..pos], Some(&args[pos + 2..])),
+        None => (args, None),
+    };
+
+    let mut operands = vec![];
+    for arg in split_args(reads) {
+        operands.push(parse_operand(line, &arg)?);
+    }
+
+    match (instruction.writes(), target) {
+        (true, Some(target)) => {
+            let target = parse_operand(line, target)?;
+            if target.mode == 1 {
+                return Err(AsmError::new(line, String::from("write target can't be immediate")));
+            }
+            operands.push(target);
+        },
+        (true, None) => {
+            return Err(AsmError::new(line, format!("{} needs a '->' target", mnemonic)));
+        },
+        (false, Some(_)) => {
+            return Err(AsmError::new(line, format!("{} doesn't write to memory", mnemonic)));
+        },
+        (false, None) => (),
+    }
+
+    let expected = instruction.len().unwrap() - 1;
+    if operands.len() != expected {
+        return Err(AsmError::new(line, format!(
+            "{} takes {} operands, got {}",
+            mnemonic,
+            expected,
+            operands.len(),
+        )));
+    }
+
+    Ok(Item::Instruction(opcode, operands))
+}
+
+fn parse_statement(line: usize, text: &str) -> Result<Statement, AsmError> {
+    let (label_text, body) = split_labels(text);
+    let mut labels = vec![];
+    let mut address = None;
+
+    for label in label_text.split(':').map(str::trim).filter(|l| !l.is_empty()) {
+        match label.parse() {
+            Ok(addr) => address = Some(addr),
+            Err(_) => labels.push(String::from(label)),
+        }
+    }
+
+    let item = if body.is_empty() {
+        None
+    } else {
+        let (first, rest) = split_first_word(body);
+        if first.eq_ignore_ascii_case(".data") {
+            let mut values = vec![];
+            for arg in split_args(rest) {
+                values.push(parse_expr(line, &arg)?);
+            }
+            Some(Item::Data(values))
+        } else if first.starts_with('.') {
+            return Err(AsmError::new(line, format!("unknown directive '{}'", first)));
+        } else {
+            Some(parse_instruction(line, first, rest)?)
+        }
+    };
+
+    Ok(Statement { line, labels, address, item })
+}
+
+fn resolve(line: usize, expr: &Expr, labels: &HashMap<String, usize>) -> Result<i64, AsmError> {
+    match expr {
+        Expr::Number(value) => Ok(*value),
+        Expr::Label(name, offset) => match labels.get(name) {
+            Some(&addr) => (addr as i64).checked_add(*offset).ok_or_else(|| {
+                AsmError::new(line, format!("'{}{:+}' overflows", name, offset))
+            }),
+            None => Err(AsmError::new(line, format!("undefined label '{}'", name))),
+        },
+    }
+}
+
+/// Assembles source text into a memory image.
+///
+/// Each line holds an optional list of `label:` definitions followed by an
+/// instruction or directive.  Instructions use the same syntax as the
+/// disassembler (`ADD [12], #5 -> rb+3`), where `#` marks immediate
+/// operands, `[..]` position operands and `rb+N` relative operands, and
+/// labels can be used wherever a number is expected.  A numeric label like
+/// `12:` asserts the current address, so a disassembler listing can be fed
+/// straight back in.  Write targets can't be immediate, which is why the
+/// disassembler lists instructions with one as data.
+///
+/// Directives:
+///
+/// - `.data 1, 2, label` emits raw words
+/// - `.macro name a, b` ... `.endm` defines a macro, whose body refers to
+///   its arguments as `\a` and `\b`, and may use `\@` to get a number that
+///   is unique to each expansion
+pub fn assemble(source: &str) -> Result<Vec<i64>, AsmError> {
+    let mut statements = vec![];
+    for (line, text) in preprocess(source)? {
+        statements.push(parse_statement(line, &text)?);
+    }
+
+    let mut labels = HashMap::new();
+    let mut addr = 0;
+    for statement in &statements {
+        if let Some(expected) = statement.address {
+            if expected != addr {
+                return Err(AsmError::new(statement.line, format!(
+                    "expected address {}, but assembling at {}",
+                    expected,
+                    addr,
+                )));
+            }
+        }
+
+        for label in &statement.labels {
+            if labels.insert(label.clone(), addr).is_some() {
+                return Err(AsmError::new(statement.line, format!("duplicate label '{}'", label)));
+            }
+        }
+
+        addr += match &statement.item {
+            Some(Item::Instruction(_, operands)) => operands.len() + 1,
+            Some(Item::Data(values)) => values.len(),
+            None => 0,
+        };
+    }
+
+    let mut memory = vec![];
+    for statement in &statements {
+        match &statement.item {
+            Some(Item::Instruction(opcode, operands)) => {
+                let mut word = *opcode;
+                for (i, operand) in operands.iter().enumerate() {
+                    word += operand.mode * 10i64.pow(i as u32 + 2);
+                }
+                memory.push(word);
+
+                for operand in operands {
+                    memory.push(resolve(statement.line, &operand.expr, &labels)?);
+                }
+            },
+            Some(Item::Data(values)) => {
+                for value in values {
+                    memory.push(resolve(statement.line, value, &labels)?);
+                }
+            },
+            None => (),
+        }
+    }
+
+    Ok(memory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::disasm::listing;
+
+    #[test]
+    fn listing_assembles_back() {
+        for day in &[include_str!("../../inputs/09.txt"), include_str!("../../inputs/25.txt")] {
+            let memory: Vec<i64> = day.trim().split(',').map(|x| x.parse().unwrap()).collect();
+            assert_eq!(assemble(&listing(&memory)).unwrap(), memory);
+        }
+    }
+
+    #[test]
+    fn random_listings_assemble_back() {
+        // A linear congruential generator, so failures can be reproduced
+        let mut state: u64 = 1;
+        let mut next = move |bound: u64| {
+            state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+            (state >> 33) % bound
+        };
+
+        for _ in 0..20_000 {
+            let len = next(16) as usize + 1;
+            let memory: Vec<i64> = (0..len)
+                .map(|_| match next(4) {
+                    0 => next(200_000) as i64 - 100_000,
+                    1 => [i64::MIN, i64::MAX, -1, 0][next(4) as usize],
+                    _ => {
+                        let opcode = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99][next(10) as usize];
+                        opcode + 100 * next(1000) as i64
+                    },
+                })
+                .collect();
+            assert_eq!(assemble(&listing(&memory)), Ok(memory));
+        }
+    }
+
+    #[test]
+    fn labels_and_macros() {
+        let memory = assemble(
+            "
+            .macro out_const value
+                    OUT #\\value
+            .endm
+                    out_const 7
+            loop:   JT #1, #loop
+            end:    .data end, loop + 1
+            ",
+        )
+        .unwrap();
+        assert_eq!(memory, [104, 7, 1105, 1, 2, 5, 3]);
+
+        let memory = assemble(
+            "
+            .macro pair a, ab
+                    .data \\ab, \\a
+            .endm
+                    pair 1, 2
+            ",
+        )
+        .unwrap();
+        assert_eq!(memory, [2, 1]);
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        let err = assemble("OUT #1\nADD #1 -> [0]").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(assemble("JT #1, #nowhere").unwrap_err().line, 1);
+        assert_eq!(assemble("x: HLT\nx: HLT").unwrap_err().line, 2);
+        assert_eq!(assemble("3: HLT").unwrap_err().line, 1);
+        assert_eq!(assemble("HLT\nx: .data x + 9223372036854775807").unwrap_err().line, 2);
+    }
+}
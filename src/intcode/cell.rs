@@ -0,0 +1,196 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Add, Mul};
+use num::{BigInt, CheckedAdd, CheckedMul, ToPrimitive, Zero};
+
+/// The type of a memory cell.
+///
+/// Programs are usually run with `i64` cells, which wrap around on
+/// overflow unless `Program::set_checked` is on.  `i128` gives more
+/// headroom, and `BigInt` never overflows at the cost of speed.  Whatever
+/// the cell type, opcodes, addresses, immediate parameters and the
+/// relative base must fit in an `i64`.  The default value must be zero,
+/// since that's what unwritten memory reads as.
+pub trait Cell:
+    Clone
+    + Default
+    + PartialEq
+    + PartialOrd
+    + fmt::Debug
+    + fmt::Display
+    + Add<Output = Self>
+    + Mul<Output = Self>
+    + Send
+    + Sync
+    + 'static
+{
+    fn from_i64(value: i64) -> Self;
+
+    /// The value as an `i64`, or `None` if it doesn't fit.
+    fn to_i64(&self) -> Option<i64>;
+
+    fn is_zero(&self) -> bool;
+
+    /// Adds, returning `None` on overflow.
+    fn add_checked(&self, other: &Self) -> Option<Self>;
+
+    /// Multiplies, returning `None` on overflow.
+    fn mul_checked(&self, other: &Self) -> Option<Self>;
+
+    /// Adds, wrapping around on overflow.
+    fn wrapping_add(&self, other: &Self) -> Self;
+
+    /// Multiplies, wrapping around on overflow.
+    fn wrapping_mul(&self, other: &Self) -> Self;
+}
+
+impl Cell for i64 {
+    #[inline]
+    fn from_i64(value: i64) -> i64 {
+        value
+    }
+
+    #[inline]
+    fn to_i64(&self) -> Option<i64> {
+        Some(*self)
+    }
+
+    #[inline]
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+
+    #[inline]
+    fn add_checked(&self, other: &i64) -> Option<i64> {
+        i64::checked_add(*self, *other)
+    }
+
+    #[inline]
+    fn mul_checked(&self, other: &i64) -> Option<i64> {
+        i64::checked_mul(*self, *other)
+    }
+
+    #[inline]
+    fn wrapping_add(&self, other: &i64) -> i64 {
+        i64::wrapping_add(*self, *other)
+    }
+
+    #[inline]
+    fn wrapping_mul(&self, other: &i64) -> i64 {
+        i64::wrapping_mul(*self, *other)
+    }
+}
+
+impl Cell for i128 {
+    fn from_i64(value: i64) -> i128 {
+        i128::from(value)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        i64::try_from(*self).ok()
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+
+    fn add_checked(&self, other: &i128) -> Option<i128> {
+        i128::checked_add(*self, *other)
+    }
+
+    fn mul_checked(&self, other: &i128) -> Option<i128> {
+        i128::checked_mul(*self, *other)
+    }
+
+    fn wrapping_add(&self, other: &i128) -> i128 {
+        i128::wrapping_add(*self, *other)
+    }
+
+    fn wrapping_mul(&self, other: &i128) -> i128 {
+        i128::wrapping_mul(*self, *other)
+    }
+}
+
+impl Cell for BigInt {
+    fn from_i64(value: i64) -> BigInt {
+        BigInt::from(value)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        ToPrimitive::to_i64(self)
+    }
+
+    fn is_zero(&self) -> bool {
+        Zero::is_zero(self)
+    }
+
+    fn add_checked(&self, other: &BigInt) -> Option<BigInt> {
+        CheckedAdd::checked_add(self, other)
+    }
+
+    fn mul_checked(&self, other: &BigInt) -> Option<BigInt> {
+        CheckedMul::checked_mul(self, other)
+    }
+
+    // BigInts never overflow, so there's nothing to wrap
+    fn wrapping_add(&self, other: &BigInt) -> BigInt {
+        self + other
+    }
+
+    fn wrapping_mul(&self, other: &BigInt) -> BigInt {
+        self * other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{Program, VmError};
+
+    // MUL #max, #2 -> [0]; OUT [0]; HLT
+    fn doubling<T: Cell>(max: T) -> Program<T> {
+        let memory: Vec<T> = vec![1102, 0, 2, 0, 4, 0, 99].into_iter().map(T::from_i64).collect();
+        let mut program = Program::new(&memory);
+        program.store(1, max);
+        program
+    }
+
+    #[test]
+    fn unchecked_overflow_wraps() {
+        let mut program = doubling(i64::MAX);
+        program.run().unwrap();
+        assert_eq!(program.drain_outputs(), [-2]);
+    }
+
+    #[test]
+    fn checked_overflow_is_an_error() {
+        let mut program = doubling(i64::MAX);
+        program.set_checked(true);
+        assert_eq!(program.run(), Err(VmError::Overflow { ip: 0, instruction: 1102 }));
+    }
+
+    #[test]
+    fn checked_relative_address_overflow_is_an_error() {
+        // ARB #max; OUT rb+1; HLT
+        let mut program = Program::new(&[109, i64::MAX, 204, 1, 99]);
+        program.set_checked(true);
+        assert_eq!(program.run(), Err(VmError::Overflow { ip: 2, instruction: 204 }));
+
+        let mut program = Program::new(&[109, i64::MAX, 109, 1, 99]);
+        program.set_checked(true);
+        assert_eq!(program.run(), Err(VmError::Overflow { ip: 2, instruction: 109 }));
+    }
+
+    #[test]
+    fn wider_cells_dont_overflow() {
+        let mut program = doubling(i128::from(i64::MAX));
+        program.set_checked(true);
+        program.run().unwrap();
+        assert_eq!(program.drain_outputs(), [i128::from(i64::MAX) * 2]);
+
+        let mut program = doubling(BigInt::from(i64::MAX));
+        program.set_checked(true);
+        program.run().unwrap();
+        assert_eq!(program.drain_outputs(), [BigInt::from(i64::MAX) * 2]);
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::{BTreeMap, BTreeSet};
+use super::{Instruction, Param};
+use super::disasm::{decode_in, find_code, is_unconditional_jump, pushed_return_address};
+
+/// How control leaves a basic block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// Runs into the block starting at the given address
+    Fallthrough(usize),
+    Jump(usize),
+    /// A conditional jump with a known target
+    Branch { target: usize, fallthrough: usize },
+    /// A jump to an address only known at run time, like a return.  Has a
+    /// fallthrough if the jump is conditional.
+    Computed { fallthrough: Option<usize> },
+    /// A call sequence: the return address is pushed and the target jumped
+    /// to.  The target isn't known if it's computed, like for callbacks.
+    Call { target: Option<usize>, ret: usize },
+    Halt,
+    /// Runs into something that isn't code
+    End,
+}
+
+impl Exit {
+    /// Addresses of the blocks control can go to.
+    pub fn targets(&self) -> Vec<usize> {
+        match *self {
+            Exit::Fallthrough(next) | Exit::Jump(next) => vec![next],
+            Exit::Branch { target, fallthrough } => vec![target, fallthrough],
+            Exit::Computed { fallthrough } => fallthrough.into_iter().collect(),
+            Exit::Call { target, ret } => target.into_iter().chain(Some(ret)).collect(),
+            Exit::Halt | Exit::End => vec![],
+        }
+    }
+}
+
+/// A straight run of instructions, only entered at the top.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    /// Start addresses of the instructions in the block
+    pub instructions: Vec<usize>,
+    pub exit: Exit,
+    /// How much the block moves the relative base, if it's only moved by
+    /// constants
+    pub rb_delta: Option<i64>,
+}
+
+/// The control-flow graph of a memory image.
+///
+/// Only code found by `disasm::find_code` is included.  Functions are the
+/// targets of call sequences, plus the entry point at address 0.  A
+/// function's frame size is taken from an `ARB` with a constant at its
+/// entry, which is how the puzzle programs set up their stack frames.
+#[derive(Debug, Clone)]
+pub struct Cfg {
+    pub blocks: BTreeMap<usize, Block>,
+    pub functions: BTreeSet<usize>,
+}
+
+fn exit_of(
+    memory: &[i64],
+    instruction: &Instruction,
+    addr: usize,
+    previous: Option<usize>,
+) -> Option<Exit> {
+    let next = addr + instruction.params.len() + 1;
+
+    match (instruction.opcode, &instruction.params[..]) {
+        (99, _) => Some(Exit::Halt),
+        (5, [cond, target]) | (6, [cond, target]) => {
+            let jumps_on_nonzero = instruction.opcode == 5;
+            let always = is_unconditional_jump(instruction);
+            let never = match cond {
+                Param::Immediate(cond) => (*cond != 0) != jumps_on_nonzero,
+                _ => false,
+            };
+            if never {
+                return None;
+            }
+
+            let call = previous
+                .and_then(|prev| decode_in(memory, prev).map(|i| (prev, i)))
+                .and_then(|(prev, i)| pushed_return_address(memory, &i, prev))
+                .filter(|_| always);
+
+            let exit = match (target, call) {
+                (Param::Immediate(target), call) if *target >= 0 => {
+                    let target = *target as usize;
+                    match call {
+                        Some(ret) => Exit::Call { target: Some(target), ret },
+                        None if always => Exit::Jump(target),
+                        None => Exit::Branch { target, fallthrough: next },
+                    }
+                },
+                (_, Some(ret)) => Exit::Call { target: None, ret },
+                _ if always => Exit::Computed { fallthrough: None },
+                _ => Exit::Computed { fallthrough: Some(next) },
+            };
+            Some(exit)
+        },
+        _ => None,
+    }
+}
+
+impl Cfg {
+    pub fn new(memory: &[i64]) -> Cfg {
+        let code = find_code(memory);
+
+        // Every instruction that can be jumped to, or follows a jump,
+        // starts a block
+        let mut leaders = BTreeSet::new();
+        leaders.insert(0);
+        let mut previous = None;
+        for &addr in &code {
+            let instruction = decode_in(memory, addr).unwrap();
+            if let Some(exit) = exit_of(memory, &instruction, addr, previous) {
+                leaders.extend(exit.targets());
+                leaders.insert(addr + instruction.params.len() + 1);
+            }
+            previous = Some(addr);
+        }
+        leaders.retain(|addr| code.contains(addr));
+
+        let mut blocks = BTreeMap::new();
+        let mut functions = BTreeSet::new();
+        functions.insert(0);
+
+        for &start in &leaders {
+            let mut block = Block {
+                start,
+                instructions: vec![],
+                exit: Exit::End,
+                rb_delta: Some(0),
+            };
+            let mut addr = start;
+            let mut previous = None;
+
+            while code.contains(&addr) && (addr == start || !leaders.contains(&addr)) {
+                let instruction = decode_in(memory, addr).unwrap();
+                let next = addr + instruction.params.len() + 1;
+                block.instructions.push(addr);
+
+                if instruction.opcode == 9 {
+                    block.rb_delta = match (block.rb_delta, &instruction.params[0]) {
+                        (Some(delta), Param::Immediate(offset)) => delta.checked_add(*offset),
+                        _ => None,
+                    };
+                }
+
+                if let Some(exit) = exit_of(memory, &instruction, addr, previous) {
+                    block.exit = exit;
+                    break;
+                }
+
+                previous = Some(addr);
+                addr = next;
+                block.exit = if code.contains(&addr) { Exit::Fallthrough(addr) } else { Exit::End };
+            }
+
+            if let Exit::Call { target: Some(target), .. } = block.exit {
+                functions.insert(target);
+            }
+            blocks.insert(start, block);
+        }
+
+        Cfg { blocks, functions }
+    }
+
+    /// The frame size set up at a function's entry, if any.
+    pub fn frame_size(&self, memory: &[i64], function: usize) -> Option<i64> {
+        let block = self.blocks.get(&function)?;
+        let instruction = decode_in(memory, *block.instructions.first()?)?;
+
+        match (instruction.opcode, &instruction.params[..]) {
+            (9, [Param::Immediate(size)]) => Some(*size),
+            _ => None,
+        }
+    }
+
+    /// Renders the graph in Graphviz DOT format, with each block labelled
+    /// with its disassembly.  Function entries are drawn with a double
+    /// border, branches are labelled with when they're taken, and calls
+    /// are dotted.
+    pub fn to_dot(&self, memory: &[i64]) -> String {
+        let mut dot = String::from("digraph cfg {\n");
+        dot += "    node [shape=box, fontname=\"monospace\"];\n";
+
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            if self.functions.contains(&block.start) {
+                label += &format!("function {}", block.start);
+                if let Some(size) = self.frame_size(memory, block.start) {
+                    label += &format!(" (frame {})", size);
+                }
+                label += "\\l";
+            }
+            for &addr in &block.instructions {
+                let text = decode_in(memory, addr).unwrap().to_string();
+                label += &format!("{:>5}: {}\\l", addr, text.replace('"', "\\\""));
+            }
+            match block.exit {
+                Exit::Computed { .. } => label += "-> computed\\l",
+                Exit::Call { target: None, .. } => label += "-> computed call\\l",
+                Exit::End => label += "-> end of code\\l",
+                _ => (),
+            }
+
+            let peripheries = if self.functions.contains(&block.start) { 2 } else { 1 };
+            dot += &format!(
+                "    b{} [label=\"{}\", peripheries={}];\n",
+                block.start, label, peripheries,
+            );
+        }
+
+        for block in self.blocks.values() {
+            let from = block.start;
+            let last = *block.instructions.last().unwrap();
+            let branch_label = match decode_in(memory, last).map(|i| i.opcode) {
+                Some(5) => ("nonzero", "zero"),
+                _ => ("zero", "nonzero"),
+            };
+
+            let edges = match block.exit {
+                Exit::Fallthrough(next) | Exit::Jump(next) => vec![(next, "")],
+                Exit::Branch { target, fallthrough } => vec![
+                    (target, branch_label.0),
+                    (fallthrough, branch_label.1),
+                ],
+                Exit::Computed { fallthrough: Some(next) } => vec![(next, "")],
+                Exit::Call { target, ret } => {
+                    if let Some(target) = target {
+                        dot += &format!(
+                            "    b{} -> b{} [style=dotted, label=\"call\"];\n",
+                            from, target,
+                        );
+                    }
+                    vec![(ret, "return")]
+                },
+                _ => vec![],
+            };
+
+            for (to, label) in edges {
+                if self.blocks.contains_key(&to) {
+                    dot += &format!("    b{} -> b{} [label=\"{}\"];\n", from, to, label);
+                }
+            }
+        }
+
+        dot += "}\n";
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calls_split_blocks() {
+        // main: ADD #7, #0 -> rb+0; JT #1, #8; HLT; f: HLT
+        let memory = [21101, 7, 0, 0, 1105, 1, 8, 99, 99];
+        let cfg = Cfg::new(&memory);
+        assert_eq!(cfg.blocks[&0].exit, Exit::Call { target: Some(8), ret: 7 });
+        assert!(cfg.functions.contains(&8));
+    }
+
+    #[test]
+    fn overflowing_relative_base_is_unknown() {
+        let memory = [109, i64::MAX, 109, i64::MAX, 99];
+        let cfg = Cfg::new(&memory);
+        assert_eq!(cfg.blocks[&0].rb_delta, None);
+    }
+}
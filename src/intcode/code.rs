@@ -0,0 +1,144 @@
+use std::sync::Arc;
+use super::{Instruction, Param, VmError};
+use super::cell::Cell;
+use super::isa::{Builtin, Shape, MAX_PARAMS};
+
+/// A decoded instruction that fits in a few words, so executing it doesn't
+/// need an allocation the way `Instruction` does.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Op {
+    pub opcode: i64,
+    /// What the instruction does, or `None` for an extension
+    pub builtin: Option<Builtin>,
+    len: usize,
+    writes: bool,
+    params: [Param; MAX_PARAMS],
+}
+
+impl Op {
+    /// Decodes the instruction at `ip`, with `shape` giving the layout of
+    /// each opcode and `read` returning `None` for words that don't fit in
+    /// an `i64`.
+    pub fn decode<S, F>(ip: usize, shape: S, read: F) -> Result<Op, VmError>
+    where
+        S: Fn(i64) -> Option<Shape>,
+        F: Fn(usize) -> Option<i64>,
+    {
+        let raw_opcode = read(ip).ok_or(VmError::Overflow { ip, instruction: 0 })?;
+        let opcode = raw_opcode % 100;
+        let param_modifiers = raw_opcode - opcode;
+
+        let shape = match shape(opcode) {
+            Some(shape) => shape,
+            None => {
+                return Err(VmError::UnknownOpcode { ip, instruction: raw_opcode });
+            },
+        };
+        let len = shape.param_count + 1;
+
+        let mut params = [Param::Immediate(0); MAX_PARAMS];
+        for (i, param) in params.iter_mut().enumerate().take(len - 1) {
+            let value = read(ip + i + 1)
+                .ok_or(VmError::Overflow { ip, instruction: raw_opcode })?;
+            let mode_code = (param_modifiers / 10i64.pow(i as u32 + 2)) % 10;
+            *param = match Param::new(value, mode_code) {
+                Some(param) => param,
+                None => {
+                    return Err(VmError::InvalidMode {
+                        ip,
+                        instruction: raw_opcode,
+                        mode: mode_code,
+                    });
+                },
+            };
+        }
+
+        Ok(Op {
+            opcode,
+            builtin: shape.builtin,
+            len,
+            writes: shape.writes,
+            params,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn params(&self) -> &[Param] {
+        &self.params[..self.len - 1]
+    }
+
+    /// Whether the last parameter is a write target rather than a value.
+    pub fn writes(&self) -> bool {
+        self.writes
+    }
+
+    pub fn instruction(&self) -> Instruction {
+        Instruction {
+            opcode: self.opcode,
+            params: self.params().to_vec(),
+        }
+    }
+}
+
+/// Instructions decoded ahead of time from a program's initial memory.
+///
+/// Every address is decoded, whether it holds code or not, since there's
+/// no telling where execution will go.  The decoded instructions are
+/// shared between clones of a program.  When a program writes to a cell
+/// that's part of a decoded instruction, only that program marks the
+/// instruction as stale, and it's decoded from memory from then on.
+#[derive(Clone, Default)]
+pub(super) struct Code {
+    ops: Arc<Vec<Option<Op>>>,
+    stale: Vec<u64>,
+}
+
+impl Code {
+    pub fn new<T, S>(image: &[T], shape: S) -> Code
+    where
+        T: Cell,
+        S: Fn(i64) -> Option<Shape>,
+    {
+        let read = |addr: usize| image.get(addr).map_or(Some(0), Cell::to_i64);
+        let ops: Vec<Option<Op>> = (0..image.len())
+            .map(|addr| Op::decode(addr, &shape, read).ok())
+            .collect();
+
+        Code {
+            stale: vec![0; ops.len().div_ceil(64)],
+            ops: Arc::new(ops),
+        }
+    }
+
+    /// Number of addresses decoded.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    #[inline]
+    pub fn get(&self, addr: usize) -> Option<Op> {
+        match self.ops.get(addr) {
+            Some(&op) if self.stale[addr / 64] & (1 << (addr % 64)) == 0 => op,
+            _ => None,
+        }
+    }
+
+    /// Marks every decoded instruction that includes `addr` as stale.
+    #[inline]
+    pub fn invalidate(&mut self, addr: usize) {
+        if self.ops.is_empty() || addr >= self.ops.len() + MAX_PARAMS {
+            return;
+        }
+
+        for start in addr.saturating_sub(MAX_PARAMS)..=addr.min(self.ops.len() - 1) {
+            if let Some(op) = self.ops[start] {
+                if start + op.len > addr {
+                    self.stale[start / 64] |= 1 << (start % 64);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,597 @@
+use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+use super::{disasm, Instruction, Program, OPCODES};
+use super::watch::{Watch, WatchEvent};
+
+const HELP: &str = "\
+Commands:
+  s, step [n]          execute n instructions (default 1)
+  c, continue          run until a breakpoint, halt or missing input
+  record [off]         start recording, so steps can be undone, or stop
+  back [n]             step back n instructions (default 1)
+  rewind <count>       go back to after the given number of instructions
+  rewind out           go back to before the last output instruction
+  b, break [addr]      set a breakpoint on an address, or list breakpoints
+  b, break op <op>     break on an opcode (number or mnemonic)
+  d, delete [addr]     delete a breakpoint, or all of them
+  d, delete op <op>    delete an opcode breakpoint
+  w, watch [addrs] [r|w|rw]
+                       stop when memory is read or written (default w),
+                       or list watchpoints; addrs is <addr> or <a>..<b>
+  unwatch [addrs]      delete a watchpoint, or all of them
+  l, list [addr] [n]   disassemble n instructions (default: from ip)
+  x, mem <addr> [n]    show n memory cells (default 16)
+  poke <addr> <value>  write a value to memory
+  r, regs              show ip, relative base and I/O queues
+  in [values...]       queue input values, or show the input queue
+  in clear             clear the input queue
+  ascii <text>         queue a line of text as ASCII input
+  reset                restart the program, keeping breakpoints
+  save <file>          save a snapshot of the program state
+  load <file>          restore a snapshot, keeping breakpoints
+  q, quit              exit";
+
+/// An interactive debugger around a `Program`.  Commands are fed in as
+/// text lines, and each returns the text to show the user.
+pub struct Debugger {
+    program: Program,
+    initial_memory: Vec<i64>,
+    breakpoints: BTreeSet<usize>,
+    opcode_breakpoints: BTreeSet<i64>,
+    watches: Vec<(RangeInclusive<usize>, Watch)>,
+    hits: Arc<Mutex<Vec<WatchEvent>>>,
+    /// Whether to keep recording after a reset or load
+    recording: bool,
+}
+
+fn parse_number<T: std::str::FromStr>(arg: &str) -> Result<T, String> {
+    arg.parse().map_err(|_| format!("'{}' is not a valid number", arg))
+}
+
+fn parse_opcode(arg: &str) -> Result<i64, String> {
+    let opcode = match arg.parse() {
+        Ok(opcode) => Some(opcode),
+        Err(_) => OPCODES
+            .iter()
+            .cloned()
+            .find(|&op| Instruction::new(op).mnemonic().eq_ignore_ascii_case(arg)),
+    };
+
+    match opcode {
+        Some(opcode) if OPCODES.contains(&opcode) => Ok(opcode),
+        _ => Err(format!("unknown opcode '{}'", arg)),
+    }
+}
+
+fn parse_range(arg: &str) -> Result<RangeInclusive<usize>, String> {
+    match arg.find("..") {
+        Some(index) => {
+            let start = parse_number(&arg[..index])?;
+            let end = parse_number(&arg[index + 2..])?;
+            if start > end {
+                return Err(format!("'{}' is an empty range", arg));
+            }
+            Ok(start..=end)
+        },
+        None => parse_number(arg).map(|addr| addr..=addr),
+    }
+}
+
+fn format_range(range: &RangeInclusive<usize>) -> String {
+    if range.start() == range.end() {
+        range.start().to_string()
+    } else {
+        format!("{}..{}", range.start(), range.end())
+    }
+}
+
+fn format_outputs(outputs: &[i64]) -> String {
+    let is_text = outputs.len() > 1
+        && outputs.iter().all(|&value| value == 10 || (32..127).contains(&value));
+
+    if is_text {
+        let text: String = outputs.iter().map(|&value| (value as u8) as char).collect();
+        format!("output:\n{}", text.trim_end())
+    } else {
+        let values: Vec<String> = outputs.iter().map(|value| value.to_string()).collect();
+        format!("output: {}", values.join(", "))
+    }
+}
+
+impl Debugger {
+    pub fn new(memory: &[i64]) -> Debugger {
+        Debugger {
+            program: Program::new(memory),
+            initial_memory: memory.to_vec(),
+            breakpoints: BTreeSet::new(),
+            opcode_breakpoints: BTreeSet::new(),
+            watches: vec![],
+            hits: Arc::default(),
+            recording: false,
+        }
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn program_mut(&mut self) -> &mut Program {
+        &mut self.program
+    }
+
+    /// Executes a single command line and returns its output.
+    pub fn execute(&mut self, line: &str) -> String {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return String::new(),
+        };
+
+        let result = match command {
+            "s" | "step" => self.step(args),
+            "c" | "continue" => Ok(self.resume()),
+            "record" => self.record(args),
+            "back" => self.step_back(args),
+            "rewind" => self.rewind(args),
+            "b" | "break" => self.set_breakpoint(args),
+            "d" | "delete" => self.delete_breakpoint(args),
+            "w" | "watch" => self.set_watchpoint(args),
+            "unwatch" => self.delete_watchpoint(args),
+            "l" | "list" => self.list(args),
+            "x" | "mem" => self.dump(args),
+            "poke" => self.poke(args),
+            "r" | "regs" => Ok(self.registers()),
+            "in" => self.input(args),
+            "ascii" => Ok(self.ascii(line.trim()[command.len()..].trim_start())),
+            "reset" => {
+                self.program = Program::new(&self.initial_memory);
+                self.restore_recording();
+                self.install_watchpoints();
+                Ok(self.location())
+            },
+            "save" => self.save(args),
+            "load" => self.load(args),
+            "h" | "help" => Ok(String::from(HELP)),
+            _ => Err(format!("unknown command '{}', try 'help'", command)),
+        };
+
+        match result {
+            Ok(output) => output,
+            Err(message) => format!("error: {}", message),
+        }
+    }
+
+    /// Describes the instruction at the instruction pointer.
+    pub fn location(&self) -> String {
+        if !self.program.is_running() {
+            return String::from("program halted");
+        }
+        let ip = self.program.ip();
+        format!("=> {}", self.describe(ip).0)
+    }
+
+    fn describe(&self, addr: usize) -> (String, usize) {
+        let marker = if self.breakpoints.contains(&addr) { "*" } else { " " };
+
+        match disasm::decode_at(|a| self.program.read(a), addr) {
+            Some((text, len)) => (format!("{}{:>5}: {}", marker, addr, text), len),
+            None => {
+                let value = self.program.read(addr);
+                (format!("{}{:>5}: .data {}", marker, addr, value), 1)
+            },
+        }
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        let ip = self.program.ip();
+        let opcode = self.program.read(ip) % 100;
+        self.breakpoints.contains(&ip) || self.opcode_breakpoints.contains(&opcode)
+    }
+
+    fn take_outputs(&mut self) -> Option<String> {
+        let outputs = self.program.drain_outputs();
+
+        if outputs.is_empty() {
+            None
+        } else {
+            Some(format_outputs(&outputs))
+        }
+    }
+
+    /// Reports why execution stopped, along with any output produced
+    /// along the way.
+    fn stopped(&mut self, reason: &str) -> String {
+        let mut lines = vec![];
+        if let Some(outputs) = self.take_outputs() {
+            lines.push(outputs);
+        }
+        for hit in self.hits.lock().unwrap().drain(..) {
+            lines.push(format!("watchpoint: {}", hit));
+        }
+        if !reason.is_empty() {
+            lines.push(String::from(reason));
+        }
+        lines.push(self.location());
+        lines.join("\n")
+    }
+
+    fn step(&mut self, args: &[&str]) -> Result<String, String> {
+        let count = match args.first() {
+            Some(arg) => parse_number(arg)?,
+            None => 1,
+        };
+
+        for _ in 0..count {
+            if !self.program.is_running() {
+                break;
+            }
+            if self.program.needs_input() {
+                return Ok(self.stopped("waiting for input"));
+            }
+            if let Err(err) = self.program.step() {
+                return Ok(self.stopped(&format!("error: {}", err)));
+            }
+        }
+
+        Ok(self.stopped(""))
+    }
+
+    fn resume(&mut self) -> String {
+        let mut first = true;
+
+        loop {
+            if !self.program.is_running() {
+                return self.stopped("");
+            }
+            if self.program.needs_input() {
+                return self.stopped("waiting for input");
+            }
+            if !first && self.at_breakpoint() {
+                let reason = format!("breakpoint at {}", self.program.ip());
+                return self.stopped(&reason);
+            }
+            first = false;
+
+            if let Err(err) = self.program.step() {
+                return self.stopped(&format!("error: {}", err));
+            }
+            if !self.hits.lock().unwrap().is_empty() {
+                return self.stopped("");
+            }
+        }
+    }
+
+    fn record(&mut self, args: &[&str]) -> Result<String, String> {
+        match args {
+            [] => {
+                self.recording = true;
+                self.program.start_recording();
+                Ok(String::from("recording"))
+            },
+            ["off"] => {
+                self.recording = false;
+                self.program.stop_recording();
+                Ok(String::from("not recording"))
+            },
+            _ => Err(String::from("usage: record [off]")),
+        }
+    }
+
+    fn restore_recording(&mut self) {
+        if self.recording {
+            self.program.start_recording();
+        }
+    }
+
+    fn check_recording(&self) -> Result<(), String> {
+        if self.program.is_recording() {
+            Ok(())
+        } else {
+            Err(String::from("not recording, use 'record' first"))
+        }
+    }
+
+    fn step_back(&mut self, args: &[&str]) -> Result<String, String> {
+        self.check_recording()?;
+        let count = match args.first() {
+            Some(arg) => parse_number(arg)?,
+            None => 1,
+        };
+
+        for _ in 0..count {
+            if !self.program.step_back() {
+                return Ok(format!("start of recording\n{}", self.location()));
+            }
+        }
+
+        Ok(self.location())
+    }
+
+    fn rewind(&mut self, args: &[&str]) -> Result<String, String> {
+        self.check_recording()?;
+        let rewound = match args {
+            ["out"] => self.program.rewind_to_last_output(),
+            [count] => self.program.rewind_to(parse_number(count)?),
+            _ => return Err(String::from("usage: rewind <count> | rewind out")),
+        };
+
+        if rewound {
+            Ok(self.location())
+        } else {
+            Err(String::from("not within the recording"))
+        }
+    }
+
+    fn set_breakpoint(&mut self, args: &[&str]) -> Result<String, String> {
+        match args {
+            [] => Ok(self.list_breakpoints()),
+            ["op", op] => {
+                self.opcode_breakpoints.insert(parse_opcode(op)?);
+                Ok(self.list_breakpoints())
+            },
+            [addr] => {
+                self.breakpoints.insert(parse_number(addr)?);
+                Ok(self.list_breakpoints())
+            },
+            _ => Err(String::from("usage: break [addr] | break op <opcode>")),
+        }
+    }
+
+    fn delete_breakpoint(&mut self, args: &[&str]) -> Result<String, String> {
+        match args {
+            [] => {
+                self.breakpoints.clear();
+                self.opcode_breakpoints.clear();
+            },
+            ["op", op] => {
+                self.opcode_breakpoints.remove(&parse_opcode(op)?);
+            },
+            [addr] => {
+                self.breakpoints.remove(&parse_number(addr)?);
+            },
+            _ => return Err(String::from("usage: delete [addr] | delete op <opcode>")),
+        }
+
+        Ok(self.list_breakpoints())
+    }
+
+    fn list_breakpoints(&self) -> String {
+        let mut lines = vec![];
+
+        for &addr in &self.breakpoints {
+            lines.push(self.describe(addr).0);
+        }
+        for &opcode in &self.opcode_breakpoints {
+            lines.push(format!("opcode {} ({})", opcode, Instruction::new(opcode).mnemonic()));
+        }
+
+        if lines.is_empty() {
+            String::from("no breakpoints")
+        } else {
+            lines.join("\n")
+        }
+    }
+
+    /// Registers the debugger's watchpoints with the program, which is
+    /// needed whenever the program is replaced.
+    fn install_watchpoints(&mut self) {
+        self.program.clear_watchpoints();
+
+        for (range, watch) in &self.watches {
+            let hits = self.hits.clone();
+            let callback = move |event: &WatchEvent| hits.lock().unwrap().push(*event);
+            self.program.add_watchpoint(range.clone(), *watch, Arc::new(Mutex::new(callback)));
+        }
+    }
+
+    fn set_watchpoint(&mut self, args: &[&str]) -> Result<String, String> {
+        let (range, watch) = match args {
+            [] => return Ok(self.list_watchpoints()),
+            [range] => (parse_range(range)?, Watch::Writes),
+            [range, "r"] => (parse_range(range)?, Watch::Reads),
+            [range, "w"] => (parse_range(range)?, Watch::Writes),
+            [range, "rw"] => (parse_range(range)?, Watch::All),
+            _ => return Err(String::from("usage: watch [addrs] [r|w|rw]")),
+        };
+
+        self.watches.retain(|(existing, _)| *existing != range);
+        self.watches.push((range, watch));
+        self.install_watchpoints();
+        Ok(self.list_watchpoints())
+    }
+
+    fn delete_watchpoint(&mut self, args: &[&str]) -> Result<String, String> {
+        match args {
+            [] => self.watches.clear(),
+            [range] => {
+                let range = parse_range(range)?;
+                self.watches.retain(|(existing, _)| *existing != range);
+            },
+            _ => return Err(String::from("usage: unwatch [addrs]")),
+        }
+
+        self.install_watchpoints();
+        Ok(self.list_watchpoints())
+    }
+
+    fn list_watchpoints(&self) -> String {
+        if self.watches.is_empty() {
+            return String::from("no watchpoints");
+        }
+
+        let lines: Vec<String> = self.watches
+            .iter()
+            .map(|(range, watch)| {
+                let access = match watch {
+                    Watch::Reads => "r",
+                    Watch::Writes => "w",
+                    Watch::All => "rw",
+                };
+                format!("[{}] {}", format_range(range), access)
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    fn list(&self, args: &[&str]) -> Result<String, String> {
+        let mut addr = match args.first() {
+            Some(arg) => parse_number(arg)?,
+            None => self.program.ip(),
+        };
+        let count: usize = match args.get(1) {
+            Some(arg) => parse_number(arg)?,
+            None => 10,
+        };
+
+        let mut lines = vec![];
+        for _ in 0..count {
+            let (text, len) = self.describe(addr);
+            let prefix = if addr == self.program.ip() { "=>" } else { "  " };
+            lines.push(format!("{}{}", prefix, text));
+            addr = match addr.checked_add(len) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        Ok(lines.join("\n"))
+    }
+
+    fn dump(&self, args: &[&str]) -> Result<String, String> {
+        let start: usize = match args.first() {
+            Some(arg) => parse_number(arg)?,
+            None => return Err(String::from("usage: mem <addr> [count]")),
+        };
+        let count: usize = match args.get(1) {
+            Some(arg) => parse_number(arg)?,
+            None => 16,
+        };
+
+        let end = match start.checked_add(count) {
+            Some(end) => end,
+            None => return Err(format!("{} cells from {} is past the end of memory", count, start)),
+        };
+
+        let mut lines = vec![];
+        for row_start in (start..end).step_by(8) {
+            let row_end = row_start.saturating_add(8).min(end);
+            let values: Vec<String> = (row_start..row_end)
+                .map(|addr| format!("{:>8}", self.program.read(addr)))
+                .collect();
+            lines.push(format!("{:>5}: {}", row_start, values.join(" ")));
+        }
+
+        Ok(lines.join("\n"))
+    }
+
+    fn poke(&mut self, args: &[&str]) -> Result<String, String> {
+        match args {
+            [addr, value] => {
+                let addr = parse_number(addr)?;
+                let value = parse_number(value)?;
+                let old = self.program.read(addr);
+                self.program.write(addr, value);
+                Ok(format!("[{}] {} -> {}", addr, old, value))
+            },
+            _ => Err(String::from("usage: poke <addr> <value>")),
+        }
+    }
+
+    fn registers(&self) -> String {
+        let state = if !self.program.is_running() {
+            "halted"
+        } else if self.program.needs_input() {
+            "waiting for input"
+        } else {
+            "running"
+        };
+
+        format!(
+            "ip={} rb={} count={} state={}\ninputs: {:?}",
+            self.program.ip(),
+            self.program.relative_base(),
+            self.program.instruction_count(),
+            state,
+            self.program.pending_inputs(),
+        )
+    }
+
+    fn input(&mut self, args: &[&str]) -> Result<String, String> {
+        match args {
+            [] => (),
+            ["clear"] => self.program.clear_inputs(),
+            values => {
+                let mut parsed = vec![];
+                for value in values {
+                    parsed.push(parse_number(value)?);
+                }
+                self.program.push_inputs(parsed);
+            },
+        }
+
+        Ok(format!("inputs: {:?}", self.program.pending_inputs()))
+    }
+
+    fn save(&self, args: &[&str]) -> Result<String, String> {
+        match args {
+            [filename] => match self.program.save_snapshot_to_file(filename) {
+                Ok(()) => Ok(format!("saved snapshot to {}", filename)),
+                Err(err) => Err(err.to_string()),
+            },
+            _ => Err(String::from("usage: save <file>")),
+        }
+    }
+
+    fn load(&mut self, args: &[&str]) -> Result<String, String> {
+        match args {
+            [filename] => match Program::load_snapshot_from_file(filename) {
+                Ok(program) => {
+                    self.program = program;
+                    self.restore_recording();
+                    self.install_watchpoints();
+                    Ok(self.location())
+                },
+                Err(err) => Err(err.to_string()),
+            },
+            _ => Err(String::from("usage: load <file>")),
+        }
+    }
+
+    fn ascii(&mut self, text: &str) -> String {
+        self.program.push_ascii(text);
+        self.program.set_input(10);
+
+        format!("queued {} characters", text.chars().count() + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_past_the_end_of_memory() {
+        let mut debugger = Debugger::new(&[99]);
+        let max = usize::MAX;
+        assert!(debugger.execute(&format!("mem {} 10", max)).starts_with("error"));
+        assert_eq!(debugger.execute(&format!("mem {} 1", max - 1)).lines().count(), 1);
+        assert_eq!(debugger.execute(&format!("list {} 3", max)).lines().count(), 1);
+    }
+
+    #[test]
+    fn recording_is_opt_in() {
+        // ADD [0], #1 -> [0]; HLT
+        let mut debugger = Debugger::new(&[1001, 0, 1, 0, 99]);
+        debugger.execute("step");
+        assert!(debugger.execute("back").starts_with("error"));
+
+        debugger.execute("record");
+        debugger.execute("reset");
+        debugger.execute("step");
+        assert_eq!(debugger.program().read(0), 1002);
+        debugger.execute("back");
+        assert_eq!(debugger.program().read(0), 1001);
+        assert_eq!(debugger.program().ip(), 0);
+    }
+}
@@ -0,0 +1,909 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use super::{Instruction, Param};
+use super::cfg::{Cfg, Exit};
+use super::disasm::decode_in;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinOp {
+    Add,
+    Mul,
+    Lt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl BinOp {
+    fn precedence(self) -> u8 {
+        match self {
+            BinOp::Mul => 3,
+            BinOp::Add => 2,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Const(i64),
+    /// The memory cell at an address, written `[addr]` like in listings
+    Mem(Box<Expr>),
+    /// The memory cell at an offset from the relative base, written
+    /// `[rb+N]`
+    Local(Box<Expr>),
+    /// A named argument or local of the enclosing function
+    Var(String),
+    Input,
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+impl Expr {
+    fn binary(op: BinOp, a: Expr, b: Expr) -> Expr {
+        match (op, a, b) {
+            (BinOp::Add, Expr::Const(a), Expr::Const(b)) if a.checked_add(b).is_some() => {
+                Expr::Const(a + b)
+            },
+            (BinOp::Mul, Expr::Const(a), Expr::Const(b)) if a.checked_mul(b).is_some() => {
+                Expr::Const(a * b)
+            },
+            (BinOp::Lt, Expr::Const(a), Expr::Const(b)) => Expr::Const((a < b) as i64),
+            (BinOp::Eq, Expr::Const(a), Expr::Const(b)) => Expr::Const((a == b) as i64),
+            (BinOp::Add, Expr::Const(0), x) | (BinOp::Add, x, Expr::Const(0)) => x,
+            (BinOp::Mul, Expr::Const(1), x) | (BinOp::Mul, x, Expr::Const(1)) => x,
+            // Keep constants on the right, so `a - 1` reads naturally
+            (BinOp::Add, a @ Expr::Const(_), b) | (BinOp::Mul, a @ Expr::Const(_), b) => {
+                Expr::Binary(op, Box::new(b), Box::new(a))
+            },
+            (op, a, b) => Expr::Binary(op, Box::new(a), Box::new(b)),
+        }
+    }
+
+    fn negate(self) -> Expr {
+        match self {
+            Expr::Binary(BinOp::Lt, a, b) => Expr::Binary(BinOp::Ge, a, b),
+            Expr::Binary(BinOp::Ge, a, b) => Expr::Binary(BinOp::Lt, a, b),
+            Expr::Binary(BinOp::Eq, a, b) => Expr::Binary(BinOp::Ne, a, b),
+            Expr::Binary(BinOp::Ne, a, b) => Expr::Binary(BinOp::Eq, a, b),
+            Expr::Not(e) => *e,
+            Expr::Const(value) => Expr::Const((value == 0) as i64),
+            e => Expr::Not(Box::new(e)),
+        }
+    }
+
+    /// Replaces frame cells with the names `names` gives their offsets.
+    fn rename(self, names: &dyn Fn(i64) -> Option<String>) -> Expr {
+        match self {
+            Expr::Local(offset) => match *offset {
+                Expr::Const(offset) => match names(offset) {
+                    Some(name) => Expr::Var(name),
+                    None => Expr::Local(Box::new(Expr::Const(offset))),
+                },
+                offset => Expr::Local(Box::new(offset.rename(names))),
+            },
+            Expr::Mem(addr) => Expr::Mem(Box::new(addr.rename(names))),
+            Expr::Binary(op, a, b) => {
+                Expr::Binary(op, Box::new(a.rename(names)), Box::new(b.rename(names)))
+            },
+            Expr::Not(e) => Expr::Not(Box::new(e.rename(names))),
+            e => e,
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Binary(op, ..) => op.precedence(),
+            _ => 4,
+        }
+    }
+
+    fn fmt_operand(&self, f: &mut fmt::Formatter, min_precedence: u8) -> fmt::Result {
+        if self.precedence() < min_precedence {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Const(value) => write!(f, "{}", value),
+            Expr::Mem(addr) => write!(f, "[{}]", addr),
+            Expr::Local(offset) => match **offset {
+                Expr::Const(offset) if offset < 0 => write!(f, "[rb{}]", offset),
+                Expr::Const(offset) => write!(f, "[rb+{}]", offset),
+                ref offset => write!(f, "[rb+({})]", offset),
+            },
+            Expr::Var(name) => write!(f, "{}", name),
+            Expr::Input => write!(f, "input()"),
+            Expr::Binary(BinOp::Mul, a, b) if **b == Expr::Const(-1) => {
+                write!(f, "-")?;
+                a.fmt_operand(f, 4)
+            },
+            Expr::Binary(BinOp::Add, a, b) => {
+                a.fmt_operand(f, 2)?;
+                match **b {
+                    Expr::Const(value) if value < 0 && value != i64::MIN => {
+                        write!(f, " - {}", -value)
+                    },
+                    ref b => {
+                        write!(f, " + ")?;
+                        b.fmt_operand(f, 3)
+                    },
+                }
+            },
+            Expr::Binary(op, a, b) => {
+                let symbol = match op {
+                    BinOp::Add => "+",
+                    BinOp::Mul => "*",
+                    BinOp::Lt => "<",
+                    BinOp::Ge => ">=",
+                    BinOp::Eq => "==",
+                    BinOp::Ne => "!=",
+                };
+                a.fmt_operand(f, op.precedence())?;
+                write!(f, " {} ", symbol)?;
+                b.fmt_operand(f, op.precedence() + 1)
+            },
+            Expr::Not(e) => {
+                write!(f, "!")?;
+                e.fmt_operand(f, 4)
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Stmt {
+    Assign(Expr, Expr),
+    Output(Expr),
+    AdjustBase(Expr),
+    /// A call to a function, which is usually a constant address
+    Call(Expr, Vec<Expr>),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    Loop(Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    DoWhile(Vec<Stmt>, Expr),
+    Break,
+    Continue,
+    Return,
+    Halt,
+    Label(usize),
+    Goto(usize),
+    /// A jump to an address computed at run time
+    Jump(Expr),
+    /// Control runs off into something that isn't code
+    Unknown,
+}
+
+impl Stmt {
+    fn rename(self, names: &dyn Fn(i64) -> Option<String>) -> Stmt {
+        let all = |stmts: Vec<Stmt>| stmts.into_iter().map(|stmt| stmt.rename(names)).collect();
+        match self {
+            Stmt::Assign(target, value) => Stmt::Assign(target.rename(names), value.rename(names)),
+            Stmt::Output(value) => Stmt::Output(value.rename(names)),
+            Stmt::AdjustBase(delta) => Stmt::AdjustBase(delta.rename(names)),
+            Stmt::Call(callee, args) => Stmt::Call(
+                callee.rename(names),
+                args.into_iter().map(|arg| arg.rename(names)).collect(),
+            ),
+            Stmt::If(cond, then, otherwise) => {
+                Stmt::If(cond.rename(names), all(then), all(otherwise))
+            },
+            Stmt::Loop(body) => Stmt::Loop(all(body)),
+            Stmt::While(cond, body) => Stmt::While(cond.rename(names), all(body)),
+            Stmt::DoWhile(body, cond) => Stmt::DoWhile(all(body), cond.rename(names)),
+            Stmt::Jump(target) => Stmt::Jump(target.rename(names)),
+            stmt => stmt,
+        }
+    }
+}
+
+/// A basic block, lifted to statements.
+struct Node {
+    start: usize,
+    end: usize,
+    stmts: Vec<Stmt>,
+    exit: Exit,
+    /// When the block's conditional jump is taken
+    cond: Option<Expr>,
+    /// What a computed jump does: a return or a jump
+    computed: Option<Stmt>,
+}
+
+fn operand(
+    param: &Param,
+    cell: usize,
+    overrides: &mut HashMap<usize, Expr>,
+    patched: &BTreeSet<usize>,
+) -> Expr {
+    let value = match overrides.remove(&cell) {
+        Some(value) => value,
+        None if patched.contains(&cell) => Expr::Mem(Box::new(Expr::Const(cell as i64))),
+        None => Expr::Const(param.value()),
+    };
+
+    match param {
+        Param::Position(_) => Expr::Mem(Box::new(value)),
+        Param::Immediate(_) => value,
+        Param::Relative(_) => Expr::Local(Box::new(value)),
+    }
+}
+
+fn function_name(entry: usize) -> String {
+    if entry == 0 {
+        String::from("main")
+    } else {
+        format!("f_{}", entry)
+    }
+}
+
+/// Lifts a block's instructions to statements.
+///
+/// Programs written by the puzzle compiler read through pointers by
+/// writing an address into a parameter of a later instruction.  Those
+/// writes are folded into the instruction they modify, so
+/// `[1182] = [rb-5]` followed by `[rb-2] = [0]`, where 1182 holds the `0`,
+/// becomes `[rb-2] = [[rb-5]]`.  Parameters patched from anywhere else
+/// are read from memory, as `[[1182]]`.
+fn lift(
+    memory: &[i64],
+    block: &super::cfg::Block,
+    frame: Option<i64>,
+    patched: &BTreeSet<usize>,
+    arities: &HashMap<usize, usize>,
+) -> Node {
+    let mut stmts = vec![];
+    let mut overrides = HashMap::new();
+    let mut cond = None;
+    let mut computed = None;
+    let mut callee = None;
+    let mut end = block.start;
+
+    let instructions: Vec<(usize, Instruction)> = block.instructions
+        .iter()
+        .map(|&addr| (addr, decode_in(memory, addr).unwrap()))
+        .collect();
+
+    for (index, (addr, instruction)) in instructions.iter().enumerate() {
+        let addr = *addr;
+        let params = &instruction.params;
+        let mut read = |i: usize| operand(&params[i], addr + i + 1, &mut overrides, patched);
+        end = addr + params.len() + 1;
+
+        let stmt = match instruction.opcode {
+            1 | 2 | 7 | 8 => {
+                let op = match instruction.opcode {
+                    1 => BinOp::Add,
+                    2 => BinOp::Mul,
+                    7 => BinOp::Lt,
+                    _ => BinOp::Eq,
+                };
+                let (a, b, target) = (read(0), read(1), read(2));
+                Stmt::Assign(target, Expr::binary(op, a, b))
+            },
+            3 => Stmt::Assign(read(0), Expr::Input),
+            4 => Stmt::Output(read(0)),
+            9 => Stmt::AdjustBase(read(0)),
+            5 | 6 => {
+                let (value, target) = (read(0), read(1));
+
+                // Compare-and-jump sequences test a scratch cell that
+                // nothing else reads
+                let value = match stmts.last() {
+                    Some(Stmt::Assign(Expr::Mem(cell), expr))
+                        if value == Expr::Mem(cell.clone()) && matches!(**cell, Expr::Const(_)) =>
+                    {
+                        let expr = expr.clone();
+                        stmts.pop();
+                        expr
+                    },
+                    _ => value,
+                };
+                let taken = if instruction.opcode == 5 { value } else { value.negate() };
+
+                match block.exit {
+                    Exit::Computed { .. } => {
+                        let epilogue = frame
+                            .and_then(i64::checked_neg)
+                            .map(|size| Stmt::AdjustBase(Expr::Const(size)));
+                        computed = if target == Expr::Local(Box::new(Expr::Const(0))) {
+                            if epilogue.is_some() && stmts.last() == epilogue.as_ref() {
+                                stmts.pop();
+                            }
+                            Some(Stmt::Return)
+                        } else {
+                            Some(Stmt::Jump(target))
+                        };
+                        cond = Some(taken);
+                    },
+                    Exit::Branch { .. } => cond = Some(taken),
+                    Exit::Call { .. } => callee = Some(target),
+                    _ => (),
+                }
+                continue;
+            },
+            _ => continue,
+        };
+
+        // A write into the parameters of a later instruction
+        if let Stmt::Assign(Expr::Mem(cell), value) = &stmt {
+            if let Expr::Const(cell) = **cell {
+                let cell = cell as usize;
+                let patches_later = instructions[index + 1..]
+                    .iter()
+                    .any(|(addr, later)| cell > *addr && cell <= addr + later.params.len());
+                if patches_later {
+                    overrides.insert(cell, value.clone());
+                    continue;
+                }
+            }
+        }
+
+        match stmt {
+            Stmt::Assign(target, value) if target == value => (),
+            stmt => stmts.push(stmt),
+        }
+    }
+
+    if let (Exit::Call { ret, .. }, Some(callee)) = (block.exit, callee) {
+        let push = Stmt::Assign(Expr::Local(Box::new(Expr::Const(0))), Expr::Const(ret as i64));
+        if stmts.last() == Some(&push) {
+            stmts.pop();
+        }
+
+        // Arguments are stored just past the return address.  A function
+        // that's called directly takes as many as it reads, and any that
+        // weren't stored right before the call are whatever the cells hold.
+        let arity = match callee {
+            Expr::Const(entry) if entry >= 0 => arities.get(&(entry as usize)).copied(),
+            _ => None,
+        };
+        let limit = arity.map_or(i64::MAX, |arity| arity as i64);
+        let mut args = BTreeMap::new();
+        while let Some(Stmt::Assign(Expr::Local(offset), value)) = stmts.last() {
+            match **offset {
+                Expr::Const(offset)
+                    if (1..=limit).contains(&offset) && !args.contains_key(&offset) =>
+                {
+                    args.insert(offset, value.clone());
+                    stmts.pop();
+                },
+                _ => break,
+            }
+        }
+
+        let contiguous = args.keys().cloned().eq(1..=args.len() as i64);
+        if let Some(arity) = arity {
+            let args = (1..=arity as i64)
+                .map(|offset| {
+                    args.remove(&offset)
+                        .unwrap_or_else(|| Expr::Local(Box::new(Expr::Const(offset))))
+                })
+                .collect();
+            stmts.push(Stmt::Call(callee, args));
+        } else if !contiguous {
+            for (offset, value) in args.into_iter().rev() {
+                stmts.push(Stmt::Assign(Expr::Local(Box::new(Expr::Const(offset))), value));
+            }
+            stmts.push(Stmt::Call(callee, vec![]));
+        } else {
+            stmts.push(Stmt::Call(callee, args.into_values().collect()));
+        }
+    }
+
+    Node { start: block.start, end, stmts, exit: block.exit, cond, computed }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Context {
+    /// Where control goes after falling off the end of the region
+    follow: Option<usize>,
+    /// The innermost loop's header and exit
+    header: Option<usize>,
+    exit: Option<usize>,
+}
+
+struct Structurer<'a> {
+    nodes: &'a BTreeMap<usize, Node>,
+    labels: BTreeSet<usize>,
+}
+
+impl<'a> Structurer<'a> {
+    /// The first block starting in `from..hi`.
+    fn next_node(&self, from: usize, hi: usize) -> Option<&'a Node> {
+        if from < hi {
+            self.nodes.range(from..hi).next().map(|(_, node)| node)
+        } else {
+            None
+        }
+    }
+
+    fn jump_to(&mut self, target: usize, ctx: Context, at_end: bool) -> Option<Stmt> {
+        if at_end && Some(target) == ctx.follow {
+            None
+        } else if Some(target) == ctx.header {
+            Some(Stmt::Continue)
+        } else if Some(target) == ctx.exit {
+            Some(Stmt::Break)
+        } else if self.nodes.contains_key(&target) {
+            self.labels.insert(target);
+            Some(Stmt::Goto(target))
+        } else {
+            // Into the middle of an instruction, or code that wasn't found
+            Some(Stmt::Jump(Expr::Const(target as i64)))
+        }
+    }
+
+    /// Continues at `target` after the instructions ending at `end`.
+    fn continue_at(&mut self, end: usize, target: usize, hi: usize, ctx: Context) -> Option<Stmt> {
+        match self.next_node(end, hi) {
+            Some(next) if next.start == target => None,
+            next => self.jump_to(target, ctx, next.is_none()),
+        }
+    }
+
+    /// The end of the loop headed by the block at `start`, which is the
+    /// end of the last block in the region that can be reached from it and
+    /// jumps back to it.
+    fn loop_end(&self, start: usize, hi: usize) -> Option<usize> {
+        let successors = |node: &Node| match node.exit {
+            Exit::Call { ret, .. } => vec![ret],
+            exit => exit.targets(),
+        };
+
+        let mut reachable = BTreeSet::new();
+        let mut pending = vec![start];
+        while let Some(addr) = pending.pop() {
+            if let Some(node) = self.nodes.get(&addr).filter(|_| (start..hi).contains(&addr)) {
+                if reachable.insert(addr) {
+                    pending.extend(successors(node));
+                }
+            }
+        }
+
+        self.nodes
+            .range(start..hi)
+            .filter(|(addr, node)| reachable.contains(addr) && successors(node).contains(&start))
+            .map(|(_, node)| node.end)
+            .next_back()
+            .filter(|&end| end <= hi)
+    }
+
+    /// Structures the blocks starting in `lo..hi` into statements.
+    fn region(&mut self, lo: usize, hi: usize, ctx: Context) -> Vec<Stmt> {
+        let mut stmts = vec![];
+        let mut pos = lo;
+
+        while let Some(node) = self.next_node(pos, hi) {
+            let start = node.start;
+
+            if ctx.header != Some(start) {
+                if let Some(end) = self.loop_end(start, hi) {
+                    let inner = Context {
+                        follow: Some(start),
+                        header: Some(start),
+                        exit: Some(end),
+                    };
+                    let body = self.region(start, end, inner);
+                    let exits = breaks(&body);
+                    stmts.push(Stmt::Label(start));
+                    stmts.push(Stmt::Loop(body));
+                    if exits {
+                        stmts.extend(self.continue_at(end, end, hi, ctx));
+                    }
+                    pos = end;
+                    continue;
+                }
+                stmts.push(Stmt::Label(start));
+            }
+
+            stmts.extend(node.stmts.iter().cloned());
+            pos = node.end;
+
+            match node.exit {
+                Exit::Branch { target, fallthrough }
+                    if target >= fallthrough
+                        && (target < hi || target == hi && ctx.follow == Some(hi)) =>
+                {
+                    let cond = node.cond.clone().unwrap();
+                    let join = match self.nodes.range(fallthrough..target).next_back() {
+                        Some((_, last)) => match last.exit {
+                            Exit::Jump(join)
+                                if join > target && (join < hi || ctx.follow == Some(join)) =>
+                            {
+                                Some(join)
+                            },
+                            _ => None,
+                        },
+                        None => None,
+                    };
+                    let join = join.unwrap_or(target);
+                    let inner = Context { follow: Some(join), ..ctx };
+
+                    let then = self.region(fallthrough, target, inner);
+                    let otherwise = self.region(target, join, inner);
+                    stmts.push(Stmt::If(cond.negate(), then, otherwise));
+                    stmts.extend(self.continue_at(join, join, hi, ctx));
+                    pos = join;
+                },
+                Exit::Branch { target, fallthrough } => {
+                    let jump = self.jump_to(target, ctx, false).unwrap();
+                    stmts.push(Stmt::If(node.cond.clone().unwrap(), vec![jump], vec![]));
+                    stmts.extend(self.continue_at(node.end, fallthrough, hi, ctx));
+                },
+                Exit::Fallthrough(next) | Exit::Jump(next) | Exit::Call { ret: next, .. } => {
+                    stmts.extend(self.continue_at(node.end, next, hi, ctx));
+                },
+                Exit::Computed { fallthrough } => {
+                    let jump = node.computed.clone().unwrap();
+                    match fallthrough {
+                        Some(next) => {
+                            stmts.push(Stmt::If(node.cond.clone().unwrap(), vec![jump], vec![]));
+                            stmts.extend(self.continue_at(node.end, next, hi, ctx));
+                        },
+                        None => stmts.push(jump),
+                    }
+                },
+                Exit::Halt => stmts.push(Stmt::Halt),
+                Exit::End => stmts.push(Stmt::Unknown),
+            }
+        }
+
+        stmts
+    }
+}
+
+/// Whether a loop body can break out of the loop.
+fn breaks(stmts: &[Stmt]) -> bool {
+    stmts.iter().any(|stmt| match stmt {
+        Stmt::Break => true,
+        Stmt::If(_, then, otherwise) => breaks(then) || breaks(otherwise),
+        _ => false,
+    })
+}
+
+/// Turns loops into `while` and `do`/`while` loops where possible, and
+/// drops labels nothing jumps to.
+fn tidy(stmts: Vec<Stmt>, labels: &BTreeSet<usize>) -> Vec<Stmt> {
+    let mut tidied = vec![];
+
+    for stmt in stmts {
+        match stmt {
+            Stmt::Label(addr) if !labels.contains(&addr) => (),
+            Stmt::If(cond, then, otherwise) => {
+                let then = tidy(then, labels);
+                let otherwise = tidy(otherwise, labels);
+                match (then.is_empty(), otherwise.is_empty()) {
+                    (true, true) => (),
+                    (true, false) => tidied.push(Stmt::If(cond.negate(), otherwise, then)),
+                    _ => tidied.push(Stmt::If(cond, then, otherwise)),
+                }
+            },
+            Stmt::Loop(body) => {
+                let mut body = tidy(body, labels);
+                if body.last() == Some(&Stmt::Continue) {
+                    body.pop();
+                }
+
+                let len = body.len();
+                match (body.first(), body.get(len.wrapping_sub(2)..)) {
+                    (Some(Stmt::If(cond, then, otherwise)), _)
+                        if then[..] == [Stmt::Break] && otherwise.is_empty() =>
+                    {
+                        let cond = cond.clone().negate();
+                        tidied.push(Stmt::While(cond, body.split_off(1)));
+                    },
+                    (_, Some([Stmt::If(cond, then, otherwise), Stmt::Break]))
+                        if then[..] == [Stmt::Continue] && otherwise.is_empty() =>
+                    {
+                        let cond = cond.clone();
+                        body.truncate(len - 2);
+                        tidied.push(Stmt::DoWhile(body, cond));
+                    },
+                    _ => tidied.push(Stmt::Loop(body)),
+                }
+            },
+            stmt => tidied.push(stmt),
+        }
+    }
+
+    tidied
+}
+
+fn write_stmts(out: &mut String, stmts: &[Stmt], depth: usize) {
+    let indent = "    ".repeat(depth);
+
+    for stmt in stmts {
+        let line = match stmt {
+            Stmt::Assign(target, value) => format!("{} = {};", target, value),
+            Stmt::Output(value) => format!("output({});", value),
+            Stmt::AdjustBase(Expr::Const(delta)) if *delta < 0 && *delta != i64::MIN => {
+                format!("rb -= {};", -delta)
+            },
+            Stmt::AdjustBase(delta) => format!("rb += {};", delta),
+            Stmt::Call(callee, args) => {
+                let args: Vec<String> = args.iter().map(Expr::to_string).collect();
+                let callee = match callee {
+                    Expr::Const(entry) if *entry >= 0 => function_name(*entry as usize),
+                    callee => format!("(*{})", callee),
+                };
+                format!("{}({});", callee, args.join(", "))
+            },
+            Stmt::If(cond, then, otherwise) => {
+                *out += &format!("{}if ({}) {{\n", indent, cond);
+                write_stmts(out, then, depth + 1);
+                if !otherwise.is_empty() {
+                    *out += &format!("{}}} else {{\n", indent);
+                    write_stmts(out, otherwise, depth + 1);
+                }
+                String::from("}")
+            },
+            Stmt::Loop(body) | Stmt::While(_, body) => {
+                let cond = match stmt {
+                    Stmt::While(cond, _) => cond.to_string(),
+                    _ => String::from("1"),
+                };
+                *out += &format!("{}while ({}) {{\n", indent, cond);
+                write_stmts(out, body, depth + 1);
+                String::from("}")
+            },
+            Stmt::DoWhile(body, cond) => {
+                *out += &format!("{}do {{\n", indent);
+                write_stmts(out, body, depth + 1);
+                format!("}} while ({});", cond)
+            },
+            Stmt::Break => String::from("break;"),
+            Stmt::Continue => String::from("continue;"),
+            Stmt::Return => String::from("return;"),
+            Stmt::Halt => String::from("halt();"),
+            Stmt::Label(addr) => {
+                *out += &format!("{}L{}:\n", "    ".repeat(depth.saturating_sub(1)), addr);
+                continue;
+            },
+            Stmt::Goto(addr) => format!("goto L{};", addr),
+            Stmt::Jump(target) => format!("jump({});", target),
+            Stmt::Unknown => String::from("/* runs into data */"),
+        };
+        *out += &format!("{}{}\n", indent, line);
+    }
+}
+
+/// The blocks of the function at `entry`: those reachable from it
+/// without following calls.
+fn body(cfg: &Cfg, entry: usize) -> BTreeSet<usize> {
+    let mut starts = BTreeSet::new();
+    let mut pending = vec![entry];
+    while let Some(addr) = pending.pop() {
+        if let Some(block) = cfg.blocks.get(&addr) {
+            if starts.insert(addr) {
+                match block.exit {
+                    Exit::Call { ret, .. } => pending.push(ret),
+                    exit => pending.extend(exit.targets()),
+                }
+            }
+        }
+    }
+    starts
+}
+
+/// The frame size of the function at `entry`, if it sets up a frame and
+/// only ever moves the relative base back by the same amount, so a frame
+/// cell has the same offset all through its body.
+fn fixed_frame(memory: &[i64], cfg: &Cfg, entry: usize) -> Option<i64> {
+    let size = cfg.frame_size(memory, entry).filter(|&size| size > 0)?;
+    let fixed = body(cfg, entry)
+        .iter()
+        .flat_map(|start| cfg.blocks[start].instructions.iter())
+        .filter(|&&addr| addr != entry)
+        .map(|&addr| decode_in(memory, addr).unwrap())
+        .filter(|instruction| instruction.opcode == 9)
+        .all(|instruction| instruction.params[..] == [Param::Immediate(-size)]);
+    if fixed {
+        Some(size)
+    } else {
+        None
+    }
+}
+
+/// The number of arguments the function at `entry` takes, if it has a
+/// fixed frame.  A call stores argument `k` at `[rb+k]`, which is
+/// `[rb-size+k]` once the function has set up its frame, and the
+/// arguments are the frame cells it reads before writing, going through
+/// its code in order.
+fn arity(memory: &[i64], cfg: &Cfg, entry: usize) -> Option<usize> {
+    let size = fixed_frame(memory, cfg, entry)?;
+    let mut written = BTreeSet::new();
+    let mut arity = 0;
+
+    for start in body(cfg, entry) {
+        for &addr in &cfg.blocks[&start].instructions {
+            let instruction = decode_in(memory, addr).unwrap();
+            let target = if instruction.writes() { instruction.params.len() } else { 0 };
+            for (i, param) in instruction.params.iter().enumerate() {
+                let slot = match *param {
+                    Param::Relative(offset) => offset.checked_add(size),
+                    _ => None,
+                };
+                match slot {
+                    Some(slot) if 0 < slot && slot < size => {
+                        if i + 1 == target {
+                            written.insert(slot);
+                        } else if !written.contains(&slot) {
+                            arity = arity.max(slot);
+                        }
+                    },
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    Some(arity as usize)
+}
+
+/// Decompiles the function at `entry` to C-like pseudo-code.
+fn function(
+    memory: &[i64],
+    cfg: &Cfg,
+    patched: &BTreeSet<usize>,
+    arities: &HashMap<usize, usize>,
+    entry: usize,
+) -> String {
+    let frame = cfg.frame_size(memory, entry).filter(|&size| size > 0);
+
+    let mut nodes: BTreeMap<usize, Node> = body(cfg, entry)
+        .iter()
+        .map(|addr| (*addr, lift(memory, &cfg.blocks[addr], frame, patched, arities)))
+        .collect();
+    if let Some(size) = frame {
+        let stmts = &mut nodes.get_mut(&entry).unwrap().stmts;
+        if stmts.first() == Some(&Stmt::AdjustBase(Expr::Const(size))) {
+            stmts.remove(0);
+        }
+    }
+
+    let lo = *nodes.keys().next().unwrap();
+    let hi = nodes.values().map(|node| node.end).max().unwrap();
+    let mut structurer = Structurer { nodes: &nodes, labels: BTreeSet::new() };
+    let ctx = Context { follow: None, header: None, exit: None };
+
+    let mut stmts = vec![];
+    if entry != lo {
+        structurer.labels.insert(entry);
+        stmts.push(Stmt::Goto(entry));
+    }
+    stmts.extend(structurer.region(lo, hi, ctx));
+    let mut stmts = tidy(stmts, &structurer.labels);
+
+    let arity = arities.get(&entry).copied().unwrap_or(0);
+    if let Some(size) = fixed_frame(memory, cfg, entry) {
+        let names = |offset: i64| match offset.checked_add(size)? {
+            slot if 0 < slot && slot <= arity as i64 => Some(format!("arg{}", slot)),
+            slot if (arity as i64) < slot && slot < size => {
+                Some(format!("local{}", slot - arity as i64))
+            },
+            _ => None,
+        };
+        stmts = stmts.into_iter().map(|stmt| stmt.rename(&names)).collect();
+    }
+    let params: Vec<String> = (1..=arity).map(|slot| format!("arg{}", slot)).collect();
+
+    let mut out = String::new();
+    match frame {
+        Some(size) => out += &format!("// {}: frame size {}\n", entry, size),
+        None => out += &format!("// {}\n", entry),
+    }
+    out += &format!("void {}({}) {{\n", function_name(entry), params.join(", "));
+    write_stmts(&mut out, &stmts, 1);
+    out += "}\n";
+    out
+}
+
+/// Decompiles a memory image to C-like pseudo-code, one function at a
+/// time.
+///
+/// Functions are found the way `Cfg` finds them, and a function's frame
+/// setup and teardown are dropped, so `[rb+N]` refers to the same cell all
+/// through its body.  In a function whose frame stays put, the frame cells
+/// it reads before writing are its arguments, `arg1` up, and the rest are
+/// locals, `local1` up; calls to it pass that many arguments, taken from
+/// `[rb+1]` up.  Loops are recovered from jumps back to an earlier block
+/// and `if`/`else` from forward conditional jumps.  Anything that doesn't
+/// fit those shapes is left as a `goto`.
+pub fn decompile(memory: &[i64]) -> String {
+    let cfg = Cfg::new(memory);
+    let instructions: Vec<(usize, Instruction)> = cfg.blocks
+        .values()
+        .flat_map(|block| block.instructions.iter())
+        .map(|&addr| (addr, decode_in(memory, addr).unwrap()))
+        .collect();
+
+    // Parameters of instructions that other instructions write to
+    let params: BTreeSet<usize> = instructions
+        .iter()
+        .flat_map(|(addr, instruction)| addr + 1..=addr + instruction.params.len())
+        .collect();
+    let patched: BTreeSet<usize> = instructions
+        .iter()
+        .filter(|(_, instruction)| instruction.writes())
+        .filter_map(|(_, instruction)| match instruction.params.last() {
+            Some(&Param::Position(addr)) if addr >= 0 => Some(addr as usize),
+            _ => None,
+        })
+        .filter(|addr| params.contains(addr))
+        .collect();
+
+    let entries: Vec<usize> = cfg.functions
+        .iter()
+        .cloned()
+        .filter(|entry| cfg.blocks.contains_key(entry))
+        .collect();
+    // `main` isn't called, so whatever it reads first was never passed
+    let arities: HashMap<usize, usize> = entries
+        .iter()
+        .filter(|&&entry| entry != 0)
+        .filter_map(|&entry| Some((entry, arity(memory, &cfg, entry)?)))
+        .collect();
+
+    entries
+        .iter()
+        .map(|&entry| function(memory, &cfg, &patched, &arities, entry))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+
+    #[test]
+    fn constants_are_folded() {
+        assert_eq!(Expr::binary(BinOp::Add, Expr::Const(2), Expr::Const(3)), Expr::Const(5));
+        assert_eq!(Expr::binary(BinOp::Mul, Expr::Const(2), Expr::Const(3)), Expr::Const(6));
+    }
+
+    #[test]
+    fn overflowing_constants_are_not_folded() {
+        let product = Expr::binary(BinOp::Mul, Expr::Const(i64::MAX), Expr::Const(2));
+        assert!(matches!(product, Expr::Binary(BinOp::Mul, ..)));
+
+        let code = decompile(&[1102, i64::MAX, 2, 9, 99]);
+        assert!(code.contains("[9] = 2 * 9223372036854775807;"));
+    }
+
+    #[test]
+    fn arguments_and_locals_are_named() {
+        let memory = assemble(
+            "
+                    ARB #100
+                    ADD #7, #0 -> rb+2
+                    ADD #3, #0 -> rb+1
+                    ADD #back, #0 -> rb+0
+                    JT #1, #sum
+            back:   ADD #9, #0 -> rb+2
+                    ADD #done, #0 -> rb+0
+                    JT #1, #sum
+            done:   OUT rb+1
+                    HLT
+            sum:    ARB #4
+                    ADD rb-3, rb-2 -> rb-1
+                    ADD rb-1, #0 -> rb-3
+                    ARB #-4
+                    JT #1, rb+0
+            ",
+        )
+        .unwrap();
+        let code = decompile(&memory);
+
+        assert!(code.contains("void f_31(arg1, arg2) {"));
+        assert!(code.contains("local1 = arg1 + arg2;"));
+        assert!(code.contains("f_31(3, 7);"));
+        // Only the second argument is stored again for the second call
+        assert!(code.contains("f_31([rb+1], 9);"));
+    }
+}
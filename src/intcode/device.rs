@@ -0,0 +1,280 @@
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use super::Program;
+use super::cell::Cell;
+use super::host::Rng;
+
+/// Something mapped into a program's memory, see `Program::map_device`.
+///
+/// Offsets are relative to the start of the mapped range.  Reads can have
+/// side effects, like taking a character of input.
+pub trait Device<T: Cell = i64>: Send {
+    fn read(&mut self, offset: usize) -> T;
+    fn write(&mut self, offset: usize, value: T);
+}
+
+pub type SharedDevice<T = i64> = Arc<Mutex<dyn Device<T>>>;
+
+#[derive(Clone)]
+struct Mapping<T: Cell> {
+    id: usize,
+    addrs: Range<usize>,
+    device: SharedDevice<T>,
+}
+
+#[derive(Clone)]
+pub(super) struct Devices<T: Cell> {
+    mappings: Vec<Mapping<T>>,
+    next_id: usize,
+}
+
+impl<T: Cell> Default for Devices<T> {
+    fn default() -> Devices<T> {
+        Devices { mappings: vec![], next_id: 0 }
+    }
+}
+
+impl<T: Cell> Devices<T> {
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+
+    /// The device mapped at `addr` and the offset into it, if any.  Later
+    /// mappings hide earlier ones.
+    #[inline]
+    pub fn get(&self, addr: usize) -> Option<(usize, &SharedDevice<T>)> {
+        if self.mappings.is_empty() {
+            return None;
+        }
+
+        self.mappings
+            .iter()
+            .rev()
+            .find(|mapping| mapping.addrs.contains(&addr))
+            .map(|mapping| (addr - mapping.addrs.start, &mapping.device))
+    }
+}
+
+impl<T: Cell> Program<T> {
+    /// Maps a device over the addresses in `addrs`, and returns an id for
+    /// unmapping it again.  From then on instructions reading or writing
+    /// those addresses go to the device instead of memory; `read` and
+    /// `write` still see the memory underneath.  Device accesses aren't
+    /// undone by stepping back, and clones of the program share the same
+    /// devices.
+    pub fn map_device(&mut self, addrs: Range<usize>, device: SharedDevice<T>) -> usize {
+        let devices = &mut self.devices;
+        let id = devices.next_id;
+        devices.next_id += 1;
+        devices.mappings.push(Mapping { id, addrs, device });
+        id
+    }
+
+    /// Unmaps a device, returning false if there was none with that id.
+    pub fn unmap_device(&mut self, id: usize) -> bool {
+        let mappings = &mut self.devices.mappings;
+        let count = mappings.len();
+        mappings.retain(|mapping| mapping.id != id);
+        mappings.len() < count
+    }
+}
+
+/// A one-cell terminal.  Writing prints values up to 255 as characters
+/// and larger ones as numbers on a line of their own.  Reading takes a
+/// byte from stdin, or -1 at the end of input.
+#[derive(Debug, Default)]
+pub struct Console;
+
+impl<T: Cell> Device<T> for Console {
+    fn read(&mut self, _offset: usize) -> T {
+        let mut byte = [0];
+        match io::stdin().read(&mut byte) {
+            Ok(1) => T::from_i64(i64::from(byte[0])),
+            _ => T::from_i64(-1),
+        }
+    }
+
+    fn write(&mut self, _offset: usize, value: T) {
+        let mut stdout = io::stdout();
+        let result = match value.to_i64() {
+            Some(ch @ 0..=255) => write!(stdout, "{}", ch as u8 as char),
+            _ => writeln!(stdout, "{}", value),
+        };
+        result.and_then(|_| stdout.flush()).unwrap_or(());
+    }
+}
+
+/// A grid of cells, stored row by row, for drawing to.  Reads give back
+/// what was last written.
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    cells: Vec<i64>,
+    dirty: bool,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            cells: vec![0; width * height],
+            dirty: false,
+        }
+    }
+
+    /// Number of cells, which is how many addresses to map it over.
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> i64 {
+        self.cells[y * self.width + x]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[i64]> {
+        self.cells.chunks(self.width.max(1))
+    }
+
+    /// Whether anything was written since the last call.
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.dirty, false)
+    }
+}
+
+impl<T: Cell> Device<T> for Framebuffer {
+    fn read(&mut self, offset: usize) -> T {
+        T::from_i64(self.cells.get(offset).cloned().unwrap_or(0))
+    }
+
+    /// Values that don't fit in an `i64` are stored as 0.
+    fn write(&mut self, offset: usize, value: T) {
+        if let Some(cell) = self.cells.get_mut(offset) {
+            *cell = value.to_i64().unwrap_or(0);
+            self.dirty = true;
+        }
+    }
+}
+
+/// Reads as the number of milliseconds since it was created or last
+/// written to.
+#[derive(Debug, Clone)]
+pub struct Timer {
+    started: Instant,
+}
+
+impl Timer {
+    pub fn new() -> Timer {
+        Timer { started: Instant::now() }
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Timer {
+        Timer::new()
+    }
+}
+
+impl<T: Cell> Device<T> for Timer {
+    fn read(&mut self, _offset: usize) -> T {
+        T::from_i64(self.started.elapsed().as_millis() as i64)
+    }
+
+    fn write(&mut self, _offset: usize, _value: T) {
+        self.started = Instant::now();
+    }
+}
+
+/// Reads as a random number from 0 to 2^31 - 1.  Writing a number seeds
+/// it, so runs can be repeated.
+#[derive(Debug, Clone)]
+pub struct Random {
+    rng: Rng,
+}
+
+impl Random {
+    pub fn new() -> Random {
+        Random { rng: Rng::from_time() }
+    }
+
+    pub fn with_seed(seed: u64) -> Random {
+        Random { rng: Rng::new(seed) }
+    }
+}
+
+impl Default for Random {
+    fn default() -> Random {
+        Random::new()
+    }
+}
+
+impl<T: Cell> Device<T> for Random {
+    fn read(&mut self, _offset: usize) -> T {
+        T::from_i64((self.rng.next() >> 33) as i64)
+    }
+
+    fn write(&mut self, _offset: usize, value: T) {
+        self.rng = Rng::new(value.to_i64().unwrap_or(0) as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::trace::TraceRecorder;
+
+    /// Reads as how many times it has been read, and keeps what's written.
+    #[derive(Default)]
+    struct Counter {
+        reads: i64,
+        writes: Vec<i64>,
+    }
+
+    impl Device for Counter {
+        fn read(&mut self, _offset: usize) -> i64 {
+            self.reads += 1;
+            self.reads
+        }
+
+        fn write(&mut self, _offset: usize, value: i64) {
+            self.writes.push(value);
+        }
+    }
+
+    #[test]
+    fn trace_records_device_accesses() {
+        // ADD [100], #10 -> [101]; OUT [100]; HLT
+        let mut program = Program::new(&[1001, 100, 10, 101, 4, 100, 99]);
+        let counter = Arc::new(Mutex::new(Counter::default()));
+        program.map_device(100..102, counter.clone());
+        let recorder = Arc::new(Mutex::new(TraceRecorder::new()));
+        program.set_tracer(recorder.clone());
+        program.run().unwrap();
+
+        let counter = counter.lock().unwrap();
+        assert_eq!(counter.reads, 2);
+        assert_eq!(counter.writes, [11]);
+        assert_eq!(program.drain_outputs(), [2]);
+
+        let recorder = recorder.lock().unwrap();
+        let events = recorder.events();
+        assert_eq!(events[0].reads, [1, 10]);
+        assert_eq!(events[0].write, Some((101, 11)));
+        assert_eq!(events[1].reads, [2]);
+    }
+}
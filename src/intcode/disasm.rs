@@ -0,0 +1,257 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use super::{Instruction, Param};
+
+const DATA_WORDS_PER_LINE: usize = 8;
+
+/// A single line of a listing: either one decoded instruction or a run of
+/// data words.
+pub struct Line {
+    pub addr: usize,
+    pub words: Vec<i64>,
+    pub text: String,
+    pub is_code: bool,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = format!("{:>5}: {}", self.addr, self.text);
+
+        if self.is_code {
+            let words: Vec<String> = self.words.iter().map(|w| w.to_string()).collect();
+            write!(f, "{:<40}; {}", text, words.join(","))
+        } else {
+            write!(f, "{}", text)
+        }
+    }
+}
+
+/// Decodes the instruction at `addr`, returning its mnemonic text and its
+/// length in words, or `None` if the word there isn't a valid instruction.
+pub fn decode_at<F: Fn(usize) -> i64>(read: F, addr: usize) -> Option<(String, usize)> {
+    match Instruction::decode(addr, read) {
+        Ok(instruction) => {
+            let len = instruction.params.len() + 1;
+            Some((instruction.to_string(), len))
+        },
+        Err(_) => None,
+    }
+}
+
+pub(super) fn decode_in(memory: &[i64], addr: usize) -> Option<Instruction> {
+    let read = |addr: usize| memory.get(addr).cloned().unwrap_or(0);
+    let instruction = Instruction::decode(addr, read).ok()?;
+
+    if addr + instruction.params.len() < memory.len() {
+        Some(instruction)
+    } else {
+        None
+    }
+}
+
+/// Whether assembling the instruction's text gives back `word`.  Decoding
+/// ignores mode digits past the last parameter, and write targets can't
+/// be immediate in assembly.
+fn reassembles_to(instruction: &Instruction, word: i64) -> bool {
+    let encoded = instruction
+        .params
+        .iter()
+        .enumerate()
+        .fold(instruction.opcode, |word, (i, param)| {
+            word + param.mode_code() * 10i64.pow(i as u32 + 2)
+        });
+    let immediate_target = instruction.writes()
+        && matches!(instruction.params.last(), Some(Param::Immediate(_)));
+
+    encoded == word && !immediate_target
+}
+
+pub(super) fn is_unconditional_jump(instruction: &Instruction) -> bool {
+    match (instruction.opcode, &instruction.params[..]) {
+        (5, [Param::Immediate(cond), _]) => *cond != 0,
+        (6, [Param::Immediate(cond), _]) => *cond == 0,
+        _ => false,
+    }
+}
+
+pub(super) fn successors(instruction: &Instruction, addr: usize) -> Vec<usize> {
+    let next = addr + instruction.params.len() + 1;
+
+    match instruction.opcode {
+        99 => vec![],
+        5 | 6 => {
+            let jumps_on_nonzero = instruction.opcode == 5;
+            let (taken, falls_through) = match instruction.params[0] {
+                Param::Immediate(cond) => {
+                    let taken = (cond != 0) == jumps_on_nonzero;
+                    (taken, !taken)
+                },
+                _ => (true, true),
+            };
+
+            let mut addrs = vec![];
+            if falls_through {
+                addrs.push(next);
+            }
+            if let (true, Param::Immediate(target)) = (taken, &instruction.params[1]) {
+                if *target >= 0 {
+                    addrs.push(*target as usize);
+                }
+            }
+            addrs
+        },
+        _ => vec![next],
+    }
+}
+
+/// Recognizes the usual call sequence, where a constant return address is
+/// pushed to `rb+0` right before an unconditional jump, and returns the
+/// pushed address.
+pub(super) fn pushed_return_address(
+    memory: &[i64],
+    instruction: &Instruction,
+    addr: usize,
+) -> Option<usize> {
+    let value = match (instruction.opcode, &instruction.params[..]) {
+        (1, [Param::Immediate(a), Param::Immediate(b), Param::Relative(0)]) => a.checked_add(*b)?,
+        (2, [Param::Immediate(a), Param::Immediate(b), Param::Relative(0)]) => a.checked_mul(*b)?,
+        _ => return None,
+    };
+    let next = decode_in(memory, addr + instruction.params.len() + 1)?;
+
+    if is_unconditional_jump(&next) && value >= 0 {
+        Some(value as usize)
+    } else {
+        None
+    }
+}
+
+/// Finds the start addresses of all instructions reachable from address 0.
+///
+/// Jumps with immediate targets are followed, and return addresses pushed
+/// by call sequences are treated as reachable.  Computed jumps can't be
+/// followed, so code only reachable through them shows up as data.
+pub fn find_code(memory: &[i64]) -> BTreeSet<usize> {
+    let mut covered = vec![false; memory.len()];
+    let mut starts = BTreeSet::new();
+    let mut pending = vec![0];
+
+    while let Some(addr) = pending.pop() {
+        if addr >= memory.len() || covered[addr] {
+            continue;
+        }
+
+        let instruction = match decode_in(memory, addr) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        let len = instruction.params.len() + 1;
+
+        if covered[addr..addr + len].iter().any(|&c| c) {
+            continue;
+        }
+
+        for c in &mut covered[addr..addr + len] {
+            *c = true;
+        }
+        starts.insert(addr);
+
+        pending.extend(successors(&instruction, addr));
+        if let Some(ret) = pushed_return_address(memory, &instruction, addr) {
+            pending.push(ret);
+        }
+    }
+
+    starts
+}
+
+/// Disassembles a memory image into listing lines.  Instructions that
+/// wouldn't assemble back to the same words are listed as data, so the
+/// listing always does.
+pub fn disassemble(memory: &[i64]) -> Vec<Line> {
+    let code = find_code(memory);
+    let mut lines = vec![];
+    let mut addr = 0;
+
+    while addr < memory.len() {
+        let instruction = Some(addr)
+            .filter(|addr| code.contains(addr))
+            .and_then(|addr| decode_in(memory, addr))
+            .filter(|instruction| reassembles_to(instruction, memory[addr]));
+
+        if let Some(instruction) = instruction {
+            let len = instruction.params.len() + 1;
+
+            lines.push(Line {
+                addr,
+                words: memory[addr..addr + len].to_vec(),
+                text: instruction.to_string(),
+                is_code: true,
+            });
+            addr += len;
+        } else {
+            let mut end = addr + 1;
+            while end < memory.len()
+                && end - addr < DATA_WORDS_PER_LINE
+                && !code.contains(&end)
+            {
+                end += 1;
+            }
+
+            let words = memory[addr..end].to_vec();
+            let values: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+            lines.push(Line {
+                addr,
+                words,
+                text: format!(".data {}", values.join(", ")),
+                is_code: false,
+            });
+            addr = end;
+        }
+    }
+
+    lines
+}
+
+/// Renders a full listing of a memory image, one line per instruction or
+/// data run.
+pub fn listing(memory: &[i64]) -> String {
+    let mut output = String::new();
+
+    for line in disassemble(memory) {
+        output += &line.to_string();
+        output += "\n";
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn call_sequence_is_recognized() {
+        // ADD #7, #0 -> rb+0; JT #1, #8; HLT; HLT
+        let memory = [21101, 7, 0, 0, 1105, 1, 8, 99, 99];
+        let instruction = decode_in(&memory, 0).unwrap();
+        assert_eq!(pushed_return_address(&memory, &instruction, 0), Some(7));
+        assert!(find_code(&memory).contains(&7));
+    }
+
+    #[test]
+    fn overflowing_return_address_is_ignored() {
+        let memory = [21102, i64::MAX, 2, 0, 1106, 0, 8, 99, 99];
+        let instruction = decode_in(&memory, 0).unwrap();
+        assert_eq!(pushed_return_address(&memory, &instruction, 0), None);
+        assert!(listing(&memory).contains("MUL"));
+    }
+
+    #[test]
+    fn words_that_dont_reassemble_are_data() {
+        // ADD #1, #2 -> #3; HLT with a stray mode digit
+        let lines = disassemble(&[11101, 1, 2, 3, 10099]);
+        let texts: Vec<&str> = lines.iter().map(|line| &line.text[..]).collect();
+        assert_eq!(texts, [".data 11101, 1, 2, 3", ".data 10099"]);
+    }
+}
@@ -0,0 +1,226 @@
+use super::Program;
+use super::cell::Cell;
+
+/// What an instruction changed, besides moving the instruction pointer.
+#[derive(Debug, Clone)]
+pub(super) enum Change<T> {
+    None,
+    Write { addr: usize, old: T },
+    Input { addr: usize, old: T, value: T },
+    Output,
+    RelativeBase(i64),
+    Halt,
+}
+
+/// Everything that changes a program's state, with enough information
+/// to undo it.
+#[derive(Debug, Clone)]
+pub(super) enum Event<T> {
+    Step { ip: usize, change: Change<T> },
+    InputQueued,
+    InputsCleared(Vec<T>),
+    OutputTaken(T),
+    Poke { addr: usize, old: T },
+}
+
+impl<T> Event<T> {
+    fn is_step(&self) -> bool {
+        matches!(self, Event::Step { .. })
+    }
+
+    fn is_output(&self) -> bool {
+        matches!(self, Event::Step { change: Change::Output, .. })
+    }
+}
+
+/// A log of undoable events, kept while recording.  Only what changed is
+/// stored, so the log grows with the number of instructions executed and
+/// not with the size of memory.
+#[derive(Debug, Clone)]
+pub(super) struct History<T> {
+    events: Vec<Event<T>>,
+    steps: usize,
+}
+
+impl<T> Default for History<T> {
+    fn default() -> History<T> {
+        History { events: vec![], steps: 0 }
+    }
+}
+
+impl<T: Cell> Program<T> {
+    /// Starts recording everything that happens to the program, so it
+    /// can be rewound.  Anything recorded so far is thrown away.
+    pub fn start_recording(&mut self) {
+        self.history = Some(History::default());
+    }
+
+    pub fn stop_recording(&mut self) {
+        self.history = None;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.history.is_some()
+    }
+
+    /// Number of recorded instructions that can be stepped back over.
+    pub fn recorded_steps(&self) -> usize {
+        self.history.as_ref().map_or(0, |history| history.steps)
+    }
+
+    pub(super) fn record(&mut self, event: Event<T>) {
+        if let Some(history) = &mut self.history {
+            if event.is_step() {
+                history.steps += 1;
+            }
+            history.events.push(event);
+        }
+    }
+
+    fn undo(&mut self) {
+        let event = match self.history.as_mut().and_then(|history| history.events.pop()) {
+            Some(event) => event,
+            None => return,
+        };
+
+        match event {
+            Event::Step { ip, change } => {
+                match change {
+                    Change::None => (),
+                    Change::Write { addr, old } => self.store(addr, old),
+                    Change::Input { addr, old, value } => {
+                        self.store(addr, old);
+                        self.inputs.push_front(value);
+                    },
+                    Change::Output => {
+                        self.outputs.pop_back();
+                    },
+                    Change::RelativeBase(old) => self.relative_base = old,
+                    Change::Halt => self.halted = false,
+                }
+                self.ip = ip;
+                self.instruction_count -= 1;
+                self.history.as_mut().unwrap().steps -= 1;
+            },
+            Event::InputQueued => {
+                self.inputs.pop_back();
+            },
+            Event::InputsCleared(inputs) => self.inputs = inputs.into(),
+            Event::OutputTaken(value) => self.outputs.push_front(value),
+            Event::Poke { addr, old } => self.store(addr, old),
+        }
+    }
+
+    fn last_event(&self) -> Option<&Event<T>> {
+        self.history.as_ref().and_then(|history| history.events.last())
+    }
+
+    /// Undoes the last instruction, along with any inputs queued or
+    /// outputs taken since.  Returns false if there's nothing recorded to
+    /// step back over.
+    pub fn step_back(&mut self) -> bool {
+        if self.recorded_steps() == 0 {
+            return false;
+        }
+
+        while let Some(event) = self.last_event() {
+            let is_step = event.is_step();
+            self.undo();
+            if is_step {
+                break;
+            }
+        }
+
+        true
+    }
+
+    /// Rewinds to the state right after `instruction_count` instructions
+    /// had been executed.  Returns false, leaving the program alone, if
+    /// that's not within what was recorded.
+    pub fn rewind_to(&mut self, instruction_count: u64) -> bool {
+        let recorded = self.recorded_steps() as u64;
+        if !self.is_recording()
+            || instruction_count > self.instruction_count
+            || instruction_count < self.instruction_count - recorded
+        {
+            return false;
+        }
+
+        while let Some(event) = self.last_event() {
+            if event.is_step() && self.instruction_count == instruction_count {
+                break;
+            }
+            self.undo();
+        }
+
+        true
+    }
+
+    /// Rewinds to just before the last recorded output instruction.
+    /// Returns false, leaving the program alone, if there is none.
+    pub fn rewind_to_last_output(&mut self) -> bool {
+        let has_output = self.history
+            .as_ref()
+            .is_some_and(|history| history.events.iter().any(Event::is_output));
+        if !has_output {
+            return false;
+        }
+
+        while let Some(event) = self.last_event() {
+            let is_output = event.is_output();
+            self.undo();
+            if is_output {
+                break;
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // IN -> [9]; ADD [9], #5 -> [10]; OUT [10]; HLT
+    const PROGRAM: [i64; 9] = [3, 9, 1001, 9, 5, 10, 4, 10, 99];
+
+    fn recorded() -> Program {
+        let mut program = Program::new(&PROGRAM);
+        program.start_recording();
+        program.set_input(7);
+        program.run().unwrap();
+        program
+    }
+
+    #[test]
+    fn step_back_undoes_writes_and_input() {
+        let mut program = recorded();
+        assert_eq!(program.recorded_steps(), 4);
+
+        assert!(program.step_back());
+        assert!(program.is_running());
+        assert!(program.step_back());
+        assert!(program.outputs.is_empty());
+        assert!(program.step_back());
+        assert_eq!(program.read(10), 0);
+        assert!(program.step_back());
+        assert_eq!(program.read(9), 0);
+        assert_eq!(program.pending_inputs(), [7]);
+        assert_eq!(program.ip(), 0);
+        assert!(!program.step_back());
+
+        program.run().unwrap();
+        assert_eq!(program.drain_outputs(), [12]);
+    }
+
+    #[test]
+    fn rewind_to_instruction_count() {
+        let mut program = recorded();
+        assert!(program.rewind_to(1));
+        assert_eq!(program.ip(), 2);
+        assert_eq!(program.read(9), 7);
+        assert_eq!(program.read(10), 0);
+        assert!(!program.rewind_to(5));
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use super::{Param, Program, VmError};
+use super::cell::Cell;
+use super::isa::Extension;
+
+/// The opcode `Program::set_host_calls` puts `SYS` on.
+pub const HOST_CALL_OPCODE: i64 = 10;
+
+/// Host call numbers used by `HostCalls::standard`.
+pub const READ_FILE: i64 = 1;
+pub const RANDOM: i64 = 2;
+pub const CLOCK: i64 = 3;
+
+/// A host function.  It gets the calling program and the argument, and
+/// returns the result, or a message saying why it failed.
+pub type HostFn<T = i64> = Arc<Mutex<dyn FnMut(&mut Program<T>, T) -> Result<T, String> + Send>>;
+
+/// Functions intcode programs can call into, by number.
+///
+/// Programs call them with `SYS call, arg -> result`: the call number and
+/// argument are read and the result written like any other parameters.
+/// Functions that need more than one argument can take the address of a
+/// block of memory.  Calling a number that isn't registered, or a
+/// function failing, stops the program with `VmError::HostCall`.
+///
+/// Install them with `Program::set_host_calls`, or register them on any
+/// opcode of an `InstructionSet`.
+#[derive(Clone, Default)]
+pub struct HostCalls<T: Cell = i64> {
+    calls: BTreeMap<i64, (String, HostFn<T>)>,
+}
+
+impl<T: Cell> HostCalls<T> {
+    pub fn new() -> HostCalls<T> {
+        HostCalls { calls: BTreeMap::new() }
+    }
+
+    /// A few generally useful calls:
+    ///
+    /// * `READ_FILE`: the argument is the address of a zero-terminated
+    ///   path.  The file's contents are queued as input, a character at a
+    ///   time, and the result is the number of characters, or -1 if the
+    ///   file can't be read.
+    /// * `RANDOM`: a random number from 0 up to the argument, which has to
+    ///   be positive.  Not suitable for anything that needs to be secure.
+    /// * `CLOCK`: milliseconds since the Unix epoch.
+    pub fn standard() -> HostCalls<T> {
+        let mut calls = HostCalls::new();
+
+        calls.register(READ_FILE, "read_file", |program: &mut Program<T>, path: T| {
+            let addr = path.to_i64().filter(|&addr| addr >= 0).ok_or("bad path address")?;
+            let path = program.read_string(addr as usize).ok_or("path isn't ASCII")?;
+            match fs::read_to_string(path) {
+                Ok(contents) => {
+                    program.push_ascii(&contents);
+                    Ok(T::from_i64(contents.chars().count() as i64))
+                },
+                Err(_) => Ok(T::from_i64(-1)),
+            }
+        });
+
+        let mut rng = Rng::from_time();
+        calls.register(RANDOM, "random", move |_: &mut Program<T>, bound: T| {
+            match bound.to_i64() {
+                Some(bound) if bound > 0 => Ok(T::from_i64((rng.next() % bound as u64) as i64)),
+                _ => Err(format!("bad bound {}", bound)),
+            }
+        });
+
+        calls.register(CLOCK, "clock", |_: &mut Program<T>, _: T| {
+            let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            Ok(T::from_i64(elapsed.as_millis() as i64))
+        });
+
+        calls
+    }
+
+    /// Registers a function under a call number, replacing any function
+    /// already registered under it.  The name is only used in errors.
+    pub fn register<F>(&mut self, call: i64, name: &str, function: F)
+    where
+        F: FnMut(&mut Program<T>, T) -> Result<T, String> + Send + 'static,
+    {
+        self.calls.insert(call, (name.to_string(), Arc::new(Mutex::new(function))));
+    }
+
+    pub fn remove(&mut self, call: i64) -> bool {
+        self.calls.remove(&call).is_some()
+    }
+
+    /// The registered call numbers and names, in order.
+    pub fn calls(&self) -> Vec<(i64, &str)> {
+        self.calls.iter().map(|(&call, (name, _))| (call, name.as_str())).collect()
+    }
+}
+
+impl<T: Cell> fmt::Debug for HostCalls<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.calls()).finish()
+    }
+}
+
+impl<T: Cell> Extension<T> for HostCalls<T> {
+    fn mnemonic(&self) -> &str {
+        "SYS"
+    }
+
+    fn param_count(&self) -> usize {
+        3
+    }
+
+    fn writes(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, program: &mut Program<T>, params: &[Param]) -> Result<(), VmError> {
+        let call = program.read_param(&params[0])?;
+        let arg = program.read_param(&params[1])?;
+
+        let (name, function) = match call.to_i64().and_then(|call| self.calls.get(&call)) {
+            Some(entry) => entry,
+            None => return Err(program.host_call_error(format!("no host call {}", call))),
+        };
+        let result = (function.lock().unwrap())(program, arg)
+            .map_err(|message| program.host_call_error(format!("{}: {}", name, message)))?;
+        program.write_param(&params[2], result)
+    }
+}
+
+impl<T: Cell> Program<T> {
+    /// Makes `SYS` run `calls`, on `HOST_CALL_OPCODE` of the current
+    /// instruction set.
+    pub fn set_host_calls(&mut self, calls: HostCalls<T>) {
+        let mut isa = self.isa.as_deref().cloned().unwrap_or_default();
+        isa.register(HOST_CALL_OPCODE, calls);
+        self.set_instruction_set(isa);
+    }
+
+    /// Reads a zero-terminated ASCII string starting at `addr`.  Returns
+    /// `None` if a cell before the terminator isn't an ASCII character.
+    pub fn read_string(&self, addr: usize) -> Option<String> {
+        let mut string = String::new();
+        for addr in addr.. {
+            match self.read(addr).to_i64()? {
+                0 => break,
+                ch @ 1..=127 => string.push(ch as u8 as char),
+                _ => return None,
+            }
+        }
+        Some(string)
+    }
+
+    fn host_call_error(&self, message: String) -> VmError {
+        VmError::HostCall { ip: self.ip, instruction: self.instruction_word(), message }
+    }
+}
+
+/// A xorshift64* generator, good enough for games and simulations.
+#[derive(Debug, Clone)]
+pub(super) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // The state must never be zero
+        Rng { state: seed | 1 }
+    }
+
+    pub fn from_time() -> Rng {
+        let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Rng::new(elapsed.as_nanos() as u64)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_calls_get_their_argument() {
+        let mut calls = HostCalls::new();
+        calls.register(7, "double", |_: &mut Program, arg: i64| Ok(arg * 2));
+        // SYS #7, #21 -> [0]; OUT [0]; HLT
+        let mut program = Program::new(&[1110, 7, 21, 0, 4, 0, 99]);
+        program.set_host_calls(calls);
+        program.run().unwrap();
+        assert_eq!(program.drain_outputs(), [42]);
+    }
+
+    #[test]
+    fn failing_host_calls_are_errors() {
+        // SYS #2, #0 -> [0], which asks for a random number below zero
+        let mut program: Program = Program::new(&[1110, 2, 0, 0, 99]);
+        program.set_host_calls(HostCalls::standard());
+        match program.run() {
+            Err(VmError::HostCall { ip: 0, message, .. }) => assert!(message.starts_with("random")),
+            result => panic!("expected a host call error, got {:?}", result),
+        }
+
+        // SYS #99, #0 -> [0]
+        let mut program: Program = Program::new(&[1110, 99, 0, 0, 99]);
+        program.set_host_calls(HostCalls::standard());
+        assert!(matches!(program.run(), Err(VmError::HostCall { .. })));
+    }
+
+    #[test]
+    fn read_file_counts_characters() {
+        let path = std::env::temp_dir().join(format!("intcode-read-file-{}", std::process::id()));
+        fs::write(&path, "héllo").unwrap();
+
+        // SYS #1, #5 -> [0]; HLT; the path
+        let mut memory: Vec<i64> = vec![1110, READ_FILE, 5, 0, 99];
+        memory.extend(path.to_str().unwrap().chars().map(|ch| ch as i64));
+        memory.push(0);
+        let mut program = Program::new(&memory);
+        program.set_host_calls(HostCalls::standard());
+        program.run().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(program.read(0), 5);
+        let expected: Vec<i64> = "héllo".chars().map(|ch| ch as i64).collect();
+        assert_eq!(program.pending_inputs(), expected);
+    }
+
+    #[test]
+    fn strings_are_read_up_to_the_terminator() {
+        let program: Program = Program::new(&[104, 105, 0, 300]);
+        assert_eq!(program.read_string(0).as_deref(), Some("hi"));
+        assert_eq!(program.read_string(3), None);
+    }
+}
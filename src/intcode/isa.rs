@@ -0,0 +1,378 @@
+use std::fmt;
+use std::sync::Arc;
+use super::{Param, Program, VmError};
+use super::cell::Cell;
+
+/// Instructions can't have more parameters than this, since that's all
+/// a decoded instruction has room for.
+pub const MAX_PARAMS: usize = 3;
+
+/// One of the instructions built into the VM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Builtin {
+    Add,
+    Mul,
+    Input,
+    Output,
+    JumpIfTrue,
+    JumpIfFalse,
+    LessThan,
+    Equals,
+    AdjustBase,
+    Halt,
+}
+
+impl Builtin {
+    pub const ALL: [Builtin; 10] = [
+        Builtin::Add,
+        Builtin::Mul,
+        Builtin::Input,
+        Builtin::Output,
+        Builtin::JumpIfTrue,
+        Builtin::JumpIfFalse,
+        Builtin::LessThan,
+        Builtin::Equals,
+        Builtin::AdjustBase,
+        Builtin::Halt,
+    ];
+
+    /// The builtin an opcode stands for in the standard instruction set.
+    pub fn standard(opcode: i64) -> Option<Builtin> {
+        match opcode {
+            1 => Some(Builtin::Add),
+            2 => Some(Builtin::Mul),
+            3 => Some(Builtin::Input),
+            4 => Some(Builtin::Output),
+            5 => Some(Builtin::JumpIfTrue),
+            6 => Some(Builtin::JumpIfFalse),
+            7 => Some(Builtin::LessThan),
+            8 => Some(Builtin::Equals),
+            9 => Some(Builtin::AdjustBase),
+            99 => Some(Builtin::Halt),
+            _ => None,
+        }
+    }
+
+    /// The builtin's opcode in the standard instruction set.
+    pub fn opcode(&self) -> i64 {
+        match self {
+            Builtin::Add => 1,
+            Builtin::Mul => 2,
+            Builtin::Input => 3,
+            Builtin::Output => 4,
+            Builtin::JumpIfTrue => 5,
+            Builtin::JumpIfFalse => 6,
+            Builtin::LessThan => 7,
+            Builtin::Equals => 8,
+            Builtin::AdjustBase => 9,
+            Builtin::Halt => 99,
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Builtin::Add => "ADD",
+            Builtin::Mul => "MUL",
+            Builtin::Input => "IN",
+            Builtin::Output => "OUT",
+            Builtin::JumpIfTrue => "JT",
+            Builtin::JumpIfFalse => "JF",
+            Builtin::LessThan => "LT",
+            Builtin::Equals => "EQ",
+            Builtin::AdjustBase => "ARB",
+            Builtin::Halt => "HLT",
+        }
+    }
+
+    pub fn param_count(&self) -> usize {
+        match self {
+            Builtin::Add | Builtin::Mul | Builtin::LessThan | Builtin::Equals => 3,
+            Builtin::JumpIfTrue | Builtin::JumpIfFalse => 2,
+            Builtin::Input | Builtin::Output | Builtin::AdjustBase => 1,
+            Builtin::Halt => 0,
+        }
+    }
+
+    /// Whether the last parameter is a write target rather than a value.
+    pub fn writes(&self) -> bool {
+        matches!(
+            self,
+            Builtin::Add | Builtin::Mul | Builtin::Input | Builtin::LessThan | Builtin::Equals
+        )
+    }
+}
+
+/// A custom instruction, added to an `InstructionSet` with `register`.
+///
+/// `execute` gets the program with its instruction pointer still on the
+/// instruction, and the decoded parameters.  It can use the program's
+/// `read_param`, `write_param`, `jump`, `push_output` and `halt` to act
+/// on it; the instruction pointer moves past the instruction afterwards
+/// unless it jumped.  Returning an error leaves the instruction pointer
+/// where it is.
+///
+/// When a program is being recorded, only the write to the target
+/// parameter of an instruction that `writes` can be stepped back over.
+pub trait Extension<T: Cell = i64>: Send + Sync {
+    fn mnemonic(&self) -> &str;
+
+    /// Number of parameters, at most `MAX_PARAMS`.
+    fn param_count(&self) -> usize;
+
+    /// Whether the last parameter is a write target rather than a value.
+    fn writes(&self) -> bool {
+        false
+    }
+
+    fn execute(&self, program: &mut Program<T>, params: &[Param]) -> Result<(), VmError>;
+}
+
+#[derive(Clone)]
+enum Entry<T: Cell> {
+    Builtin(Builtin),
+    Extension(Arc<dyn Extension<T>>),
+}
+
+/// The layout of an instruction, which is all decoding needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Shape {
+    pub param_count: usize,
+    pub writes: bool,
+    /// `None` for extensions
+    pub builtin: Option<Builtin>,
+}
+
+impl Shape {
+    pub fn standard(opcode: i64) -> Option<Shape> {
+        Builtin::standard(opcode).map(Shape::builtin)
+    }
+
+    fn builtin(builtin: Builtin) -> Shape {
+        Shape {
+            param_count: builtin.param_count(),
+            writes: builtin.writes(),
+            builtin: Some(builtin),
+        }
+    }
+}
+
+/// Maps opcodes to what they do.
+///
+/// Opcodes are the last two digits of an instruction word, so there are
+/// a hundred of them.  The standard set has the ten opcodes of the
+/// puzzles; builtins can be moved to other opcodes or removed to try out
+/// variants of the spec, and free opcodes can be given custom
+/// instructions.  Install a set with `Program::set_instruction_set`.
+#[derive(Clone)]
+pub struct InstructionSet<T: Cell = i64> {
+    entries: Vec<Option<Entry<T>>>,
+}
+
+impl<T: Cell> InstructionSet<T> {
+    /// An instruction set with no opcodes at all.
+    pub fn empty() -> InstructionSet<T> {
+        InstructionSet { entries: vec![None; 100] }
+    }
+
+    pub fn standard() -> InstructionSet<T> {
+        let mut isa = InstructionSet::empty();
+        for &builtin in &Builtin::ALL {
+            isa.set_builtin(builtin.opcode(), builtin);
+        }
+        isa
+    }
+
+    fn index(opcode: i64) -> usize {
+        assert!((0..100).contains(&opcode), "opcode {} isn't between 0 and 99", opcode);
+        opcode as usize
+    }
+
+    /// Makes `opcode` run a builtin, replacing whatever it did before.
+    pub fn set_builtin(&mut self, opcode: i64, builtin: Builtin) {
+        self.entries[InstructionSet::<T>::index(opcode)] = Some(Entry::Builtin(builtin));
+    }
+
+    /// Makes `opcode` run a custom instruction, replacing whatever it did
+    /// before.  Panics if the extension has more than `MAX_PARAMS`
+    /// parameters, or writes without having any.
+    pub fn register<E: Extension<T> + 'static>(&mut self, opcode: i64, extension: E) {
+        let count = extension.param_count();
+        assert!(count <= MAX_PARAMS, "{} has {} parameters", extension.mnemonic(), count);
+        assert!(
+            count > 0 || !extension.writes(),
+            "{} writes but has no parameters",
+            extension.mnemonic(),
+        );
+        let entry = Entry::Extension(Arc::new(extension));
+        self.entries[InstructionSet::<T>::index(opcode)] = Some(entry);
+    }
+
+    /// Removes an opcode, so running it is an unknown opcode error.
+    /// Returns false if it wasn't in the set.
+    pub fn remove(&mut self, opcode: i64) -> bool {
+        self.entries[InstructionSet::<T>::index(opcode)].take().is_some()
+    }
+
+    /// The opcodes in the set, in order.
+    pub fn opcodes(&self) -> Vec<i64> {
+        (0..100).filter(|&opcode| self.entries[opcode as usize].is_some()).collect()
+    }
+
+    fn entry(&self, opcode: i64) -> Option<&Entry<T>> {
+        match opcode {
+            0..=99 => self.entries[opcode as usize].as_ref(),
+            _ => None,
+        }
+    }
+
+    /// The builtin an opcode runs, if it runs one.
+    pub fn builtin(&self, opcode: i64) -> Option<Builtin> {
+        match self.entry(opcode)? {
+            Entry::Builtin(builtin) => Some(*builtin),
+            Entry::Extension(_) => None,
+        }
+    }
+
+    pub fn mnemonic(&self, opcode: i64) -> Option<&str> {
+        match self.entry(opcode)? {
+            Entry::Builtin(builtin) => Some(builtin.mnemonic()),
+            Entry::Extension(extension) => Some(extension.mnemonic()),
+        }
+    }
+
+    pub(super) fn shape(&self, opcode: i64) -> Option<Shape> {
+        match self.entry(opcode)? {
+            Entry::Builtin(builtin) => Some(Shape::builtin(*builtin)),
+            Entry::Extension(extension) => Some(Shape {
+                param_count: extension.param_count(),
+                writes: extension.writes(),
+                builtin: None,
+            }),
+        }
+    }
+
+    pub(super) fn extension(&self, opcode: i64) -> Option<Arc<dyn Extension<T>>> {
+        match self.entry(opcode)? {
+            Entry::Builtin(_) => None,
+            Entry::Extension(extension) => Some(Arc::clone(extension)),
+        }
+    }
+}
+
+impl<T: Cell> Default for InstructionSet<T> {
+    fn default() -> InstructionSet<T> {
+        InstructionSet::standard()
+    }
+}
+
+impl<T: Cell> fmt::Debug for InstructionSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let opcodes = self.opcodes();
+        f.debug_map()
+            .entries(opcodes.iter().map(|&opcode| (opcode, self.mnemonic(opcode).unwrap())))
+            .finish()
+    }
+}
+
+/// `DBG a`: prints the instruction pointer and a value to stderr.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DebugPrint;
+
+impl<T: Cell> Extension<T> for DebugPrint {
+    fn mnemonic(&self) -> &str {
+        "DBG"
+    }
+
+    fn param_count(&self) -> usize {
+        1
+    }
+
+    fn execute(&self, program: &mut Program<T>, params: &[Param]) -> Result<(), VmError> {
+        let value = program.read_param(&params[0])?;
+        eprintln!("{:>5}: {}", program.ip(), value);
+        Ok(())
+    }
+}
+
+/// `TRAP`: stops the program with `VmError::Trap`, like a breakpoint
+/// compiled into it.  Running on afterwards traps again, so the
+/// instruction pointer has to be moved past it with `Program::set_ip`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Trap;
+
+impl<T: Cell> Extension<T> for Trap {
+    fn mnemonic(&self) -> &str {
+        "TRAP"
+    }
+
+    fn param_count(&self) -> usize {
+        0
+    }
+
+    fn execute(&self, program: &mut Program<T>, _params: &[Param]) -> Result<(), VmError> {
+        Err(VmError::Trap { ip: program.ip(), instruction: program.instruction_word() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtins_can_move() {
+        // ADD and MUL swapped: "MUL" #6, #7 -> [0]; OUT [0]; HLT
+        let mut isa = InstructionSet::standard();
+        isa.set_builtin(1, Builtin::Mul);
+        isa.set_builtin(2, Builtin::Add);
+        let mut program: Program = Program::new(&[1101, 6, 7, 0, 4, 0, 99]);
+        program.set_instruction_set(isa);
+        program.run().unwrap();
+        assert_eq!(program.drain_outputs(), [42]);
+    }
+
+    #[test]
+    fn removed_opcodes_are_unknown() {
+        let mut isa = InstructionSet::standard();
+        assert!(isa.remove(4));
+        assert!(!isa.remove(4));
+        let mut program: Program = Program::new(&[104, 1, 99]);
+        program.set_instruction_set(isa);
+        assert_eq!(program.run(), Err(VmError::UnknownOpcode { ip: 0, instruction: 104 }));
+    }
+
+    #[test]
+    fn trap_stops_until_skipped() {
+        let mut isa = InstructionSet::standard();
+        isa.register(50, Trap);
+        assert_eq!(isa.mnemonic(50), Some("TRAP"));
+        // TRAP; OUT #1; HLT
+        let mut program: Program = Program::new(&[50, 104, 1, 99]);
+        program.set_instruction_set(isa);
+        assert_eq!(program.run(), Err(VmError::Trap { ip: 0, instruction: 50 }));
+        program.set_ip(1);
+        program.run().unwrap();
+        assert_eq!(program.drain_outputs(), [1]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_many_params_panics() {
+        struct Wide;
+
+        impl Extension for Wide {
+            fn mnemonic(&self) -> &str {
+                "WIDE"
+            }
+
+            fn param_count(&self) -> usize {
+                MAX_PARAMS + 1
+            }
+
+            fn execute(&self, _program: &mut Program, _params: &[Param]) -> Result<(), VmError> {
+                Ok(())
+            }
+        }
+
+        InstructionSet::standard().register(50, Wide);
+    }
+}
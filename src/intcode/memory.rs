@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use super::cell::Cell;
+
+const PAGE_BITS: usize = 8;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+
+/// Pages below this index live in a flat vector, higher ones in a map, so
+/// a stray write to a huge address doesn't allocate everything below it.
+const DENSE_PAGES: usize = 4096;
+
+type Page<T> = [T; PAGE_SIZE];
+
+fn empty_page<T: Cell>() -> Page<T> {
+    std::array::from_fn(|_| T::default())
+}
+
+/// Paged program memory.  Unwritten cells read as zero.
+///
+/// Pages are shared between clones and only copied when written to, so
+/// cloning a freshly loaded program is cheap no matter how large it is.
+#[derive(Clone)]
+pub struct Memory<T: Cell = i64> {
+    dense: Vec<Option<Arc<Page<T>>>>,
+    sparse: HashMap<usize, Arc<Page<T>>>,
+}
+
+impl<T: Cell> Default for Memory<T> {
+    fn default() -> Memory<T> {
+        Memory { dense: vec![], sparse: HashMap::new() }
+    }
+}
+
+impl<T: Cell> Memory<T> {
+    pub fn new(image: &[T]) -> Memory<T> {
+        let mut memory = Memory::default();
+
+        for (index, chunk) in image.chunks(PAGE_SIZE).enumerate() {
+            let mut page = empty_page();
+            page[..chunk.len()].clone_from_slice(chunk);
+            memory.insert_page(index, Arc::new(page));
+        }
+
+        memory
+    }
+
+    fn insert_page(&mut self, index: usize, page: Arc<Page<T>>) {
+        if index < DENSE_PAGES {
+            if self.dense.len() <= index {
+                self.dense.resize(index + 1, None);
+            }
+            self.dense[index] = Some(page);
+        } else {
+            self.sparse.insert(index, page);
+        }
+    }
+
+    fn page(&self, index: usize) -> Option<&Page<T>> {
+        if index < DENSE_PAGES {
+            match self.dense.get(index) {
+                Some(Some(page)) => Some(page),
+                _ => None,
+            }
+        } else {
+            self.sparse.get(&index).map(|page| &**page)
+        }
+    }
+
+    fn page_mut(&mut self, index: usize) -> &mut Page<T> {
+        let page = if index < DENSE_PAGES {
+            if self.dense.len() <= index {
+                self.dense.resize(index + 1, None);
+            }
+            self.dense[index].get_or_insert_with(|| Arc::new(empty_page()))
+        } else {
+            self.sparse.entry(index).or_insert_with(|| Arc::new(empty_page()))
+        };
+
+        Arc::make_mut(page)
+    }
+
+    #[inline]
+    pub fn read(&self, addr: usize) -> T {
+        match self.page(addr >> PAGE_BITS) {
+            Some(page) => page[addr & (PAGE_SIZE - 1)].clone(),
+            None => T::default(),
+        }
+    }
+
+    #[inline]
+    pub fn write(&mut self, addr: usize, value: T) {
+        self.page_mut(addr >> PAGE_BITS)[addr & (PAGE_SIZE - 1)] = value;
+    }
+
+    /// Returns all non-zero cells, in address order.
+    pub fn nonzero_cells(&self) -> Vec<(usize, T)> {
+        let mut indices: Vec<usize> = self.sparse.keys().cloned().collect();
+        indices.sort_unstable();
+
+        let dense = self.dense
+            .iter()
+            .enumerate()
+            .filter(|(_, page)| page.is_some())
+            .map(|(index, _)| index);
+
+        let mut cells = vec![];
+        for index in dense.chain(indices) {
+            let page = self.page(index).unwrap();
+            for (offset, value) in page.iter().enumerate() {
+                if !value.is_zero() {
+                    cells.push(((index << PAGE_BITS) + offset, value.clone()));
+                }
+            }
+        }
+
+        cells
+    }
+}
+
+fn same_page<T: Cell>(a: Option<&Arc<Page<T>>>, b: Option<&Arc<Page<T>>>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => Arc::ptr_eq(a, b) || a[..] == b[..],
+        (Some(page), None) | (None, Some(page)) => page.iter().all(Cell::is_zero),
+        (None, None) => true,
+    }
+}
+
+/// Memories are equal if every cell is.  Pages still shared between the
+/// two are skipped, so comparing a memory to an earlier clone of itself
+/// only looks at the pages written since.
+impl<T: Cell> PartialEq for Memory<T> {
+    fn eq(&self, other: &Memory<T>) -> bool {
+        let dense_len = self.dense.len().max(other.dense.len());
+        let dense_equal = (0..dense_len).all(|index| {
+            let a = self.dense.get(index).and_then(Option::as_ref);
+            let b = other.dense.get(index).and_then(Option::as_ref);
+            same_page(a, b)
+        });
+
+        dense_equal
+            && self.sparse.iter().all(|(index, page)| same_page(Some(page), other.sparse.get(index)))
+            && other.sparse.iter().all(|(index, page)| same_page(self.sparse.get(index), Some(page)))
+    }
+}
+
+impl<T: Cell + Eq> Eq for Memory<T> {}
@@ -0,0 +1,299 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use super::{Program, RunState, VmError};
+
+enum Signal {
+    Value(i64),
+    Shutdown,
+}
+
+/// A node failed while the network was running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetError {
+    pub node: usize,
+    pub error: VmError,
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "node {}: {}", self.node, self.error)
+    }
+}
+
+impl Error for NetError {}
+
+/// The result of running a network to completion.
+pub struct Report {
+    /// Values written by tapped nodes, in the order they were produced
+    pub outputs: Vec<i64>,
+    /// The nodes as they were when the network stopped
+    pub programs: Vec<Program>,
+    /// True if the network stopped because every node still running was
+    /// waiting for input that could never arrive
+    pub deadlocked: bool,
+}
+
+/// Bookkeeping shared by all node threads, used to tell when the network
+/// has stopped making progress.
+#[derive(Default)]
+struct Activity {
+    running: usize,
+    waiting: usize,
+    in_flight: usize,
+    failed: bool,
+    outputs: Vec<i64>,
+}
+
+impl Activity {
+    fn is_done(&self) -> bool {
+        self.running == 0 || self.failed || (self.waiting == self.running && self.in_flight == 0)
+    }
+}
+
+type Shared = Arc<(Mutex<Activity>, Condvar)>;
+
+struct Node {
+    program: Program,
+    input: Receiver<Signal>,
+    targets: Vec<Sender<Signal>>,
+    tapped: bool,
+    shared: Shared,
+}
+
+impl Node {
+    fn run(mut self) -> (Program, Result<(), VmError>) {
+        let result = self.run_until_stopped();
+
+        let (lock, condvar) = &*self.shared;
+        let mut activity = lock.lock().unwrap();
+
+        // Anything left in the queue will never be read, so stop counting
+        // it before dropping the receiver
+        while let Ok(signal) = self.input.try_recv() {
+            if let Signal::Value(_) = signal {
+                activity.in_flight -= 1;
+            }
+        }
+        drop(self.input);
+
+        activity.running -= 1;
+        activity.failed |= result.is_err();
+        condvar.notify_all();
+
+        (self.program, result)
+    }
+
+    fn run_until_stopped(&mut self) -> Result<(), VmError> {
+        loop {
+            match self.program.run_until_blocked()? {
+                RunState::Halted => return Ok(()),
+                RunState::Output(value) => self.send(value),
+                RunState::NeedsInput => match self.receive() {
+                    Some(value) => self.program.set_input(value),
+                    None => return Ok(()),
+                },
+            }
+        }
+    }
+
+    fn send(&self, value: i64) {
+        let (lock, _) = &*self.shared;
+        let mut activity = lock.lock().unwrap();
+
+        if self.tapped {
+            activity.outputs.push(value);
+        }
+
+        // Sending fails if the target has already stopped, in which case
+        // the value is just lost
+        for target in &self.targets {
+            if target.send(Signal::Value(value)).is_ok() {
+                activity.in_flight += 1;
+            }
+        }
+    }
+
+    fn receive(&self) -> Option<i64> {
+        let (lock, condvar) = &*self.shared;
+
+        {
+            let mut activity = lock.lock().unwrap();
+            match self.input.try_recv() {
+                Ok(Signal::Value(value)) => {
+                    activity.in_flight -= 1;
+                    return Some(value);
+                },
+                Ok(Signal::Shutdown) => return None,
+                Err(_) => {
+                    activity.waiting += 1;
+                    condvar.notify_all();
+                },
+            }
+        }
+
+        let signal = self.input.recv();
+
+        let mut activity = lock.lock().unwrap();
+        activity.waiting -= 1;
+        match signal {
+            Ok(Signal::Value(value)) => {
+                activity.in_flight -= 1;
+                Some(value)
+            },
+            _ => None,
+        }
+    }
+}
+
+/// A set of programs wired together output to input, each running on its
+/// own thread.
+///
+/// Every output of a node is sent to all nodes it's connected to.  Outputs
+/// of tapped nodes are also collected in the report.  The network runs
+/// until all nodes have halted, or until every node still running is
+/// blocked on input and nothing is left in flight.
+#[derive(Clone, Default)]
+pub struct Topology {
+    programs: Vec<Program>,
+    edges: Vec<(usize, usize)>,
+    taps: Vec<usize>,
+}
+
+impl Topology {
+    pub fn new() -> Topology {
+        Topology::default()
+    }
+
+    /// Chains the programs so the output of each one is the input of the
+    /// next.  The last program is tapped.
+    pub fn pipeline(programs: Vec<Program>) -> Topology {
+        let mut topology = Topology::new();
+        for program in programs {
+            topology.add_node(program);
+        }
+
+        let count = topology.programs.len();
+        for node in 1..count {
+            topology.connect(node - 1, node);
+        }
+        if count > 0 {
+            topology.tap(count - 1);
+        }
+
+        topology
+    }
+
+    /// Like `pipeline`, but the output of the last program is also fed
+    /// back to the first.
+    pub fn ring(programs: Vec<Program>) -> Topology {
+        let mut topology = Topology::pipeline(programs);
+
+        let count = topology.programs.len();
+        if count > 0 {
+            topology.connect(count - 1, 0);
+        }
+
+        topology
+    }
+
+    /// Adds a node to the network and returns its index.
+    pub fn add_node(&mut self, program: Program) -> usize {
+        self.programs.push(program);
+        self.programs.len() - 1
+    }
+
+    pub fn node(&self, index: usize) -> &Program {
+        &self.programs[index]
+    }
+
+    /// Gives access to a node before the network is started, for example
+    /// to queue initial inputs.
+    pub fn node_mut(&mut self, index: usize) -> &mut Program {
+        &mut self.programs[index]
+    }
+
+    pub fn len(&self) -> usize {
+        self.programs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+
+    pub fn connect(&mut self, from: usize, to: usize) {
+        assert!(from < self.programs.len() && to < self.programs.len(), "No such node");
+        self.edges.push((from, to));
+    }
+
+    pub fn tap(&mut self, node: usize) {
+        assert!(node < self.programs.len(), "No such node");
+        if !self.taps.contains(&node) {
+            self.taps.push(node);
+        }
+    }
+
+    /// Starts a thread for each node and waits for the network to stop.
+    pub fn run(self) -> Result<Report, NetError> {
+        let Topology { programs, edges, taps } = self;
+        let shared: Shared = Arc::default();
+        shared.0.lock().unwrap().running = programs.len();
+
+        let (senders, receivers): (Vec<_>, Vec<_>) = programs
+            .iter()
+            .map(|_| mpsc::channel())
+            .unzip();
+
+        let handles: Vec<_> = programs
+            .into_iter()
+            .zip(receivers)
+            .enumerate()
+            .map(|(index, (program, input))| {
+                let node = Node {
+                    program,
+                    input,
+                    targets: edges
+                        .iter()
+                        .filter(|&&(from, _)| from == index)
+                        .map(|&(_, to)| senders[to].clone())
+                        .collect(),
+                    tapped: taps.contains(&index),
+                    shared: shared.clone(),
+                };
+                thread::spawn(move || node.run())
+            })
+            .collect();
+
+        let deadlocked = {
+            let (lock, condvar) = &*shared;
+            let activity = condvar
+                .wait_while(lock.lock().unwrap(), |activity| !activity.is_done())
+                .unwrap();
+            activity.running > 0 && !activity.failed
+        };
+
+        // Wake up whatever is still waiting for input
+        for sender in &senders {
+            let _ = sender.send(Signal::Shutdown);
+        }
+
+        let mut programs = vec![];
+        let mut error = None;
+        for (node, handle) in handles.into_iter().enumerate() {
+            let (program, result) = handle.join().expect("Node thread panicked");
+            if let (Err(err), None) = (result, &error) {
+                error = Some(NetError { node, error: err });
+            }
+            programs.push(program);
+        }
+
+        if let Some(err) = error {
+            return Err(err);
+        }
+
+        let outputs = std::mem::take(&mut shared.0.lock().unwrap().outputs);
+        Ok(Report { outputs, programs, deadlocked })
+    }
+}
@@ -0,0 +1,262 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use super::Program;
+
+/// A patch error, with the (1-based) patch line it occurred on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl PatchError {
+    fn new(line: usize, message: String) -> PatchError {
+        PatchError { line, message }
+    }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for PatchError {}
+
+enum Edit {
+    Write {
+        addr: usize,
+        values: Vec<i64>,
+    },
+    /// `None` matches any word in the pattern, and leaves the word alone
+    /// in the replacement
+    Replace {
+        pattern: Vec<Option<i64>>,
+        replacement: Vec<Option<i64>>,
+    },
+}
+
+/// A list of changes to a program's memory.
+///
+/// Patches are written one edit per line, with `;` starting a comment:
+///
+/// ```text
+/// ; Insert a quarter
+/// 0: 2
+/// ; Wall off the bottom row
+/// 1606: 1*40
+/// ; Swap the operands of every add of [384] and #1
+/// replace 1001, 384, 1, _ => 101, 1, 384, _
+/// ```
+///
+/// `addr: values` writes the values starting at `addr`, where `value*n`
+/// repeats a value `n` times.  `replace` rewrites every occurrence of a
+/// sequence of words with another one of the same length, with `_`
+/// matching any word and leaving it as it was.  A `replace` that doesn't
+/// match anything is an error, so patches don't silently go stale.
+pub struct Patch {
+    edits: Vec<(usize, Edit)>,
+}
+
+fn parse_word(line: usize, word: &str) -> Result<i64, PatchError> {
+    word.parse().map_err(|_| PatchError::new(line, format!("'{}' is not a valid number", word)))
+}
+
+fn parse_values(line: usize, text: &str) -> Result<Vec<i64>, PatchError> {
+    let mut values = vec![];
+
+    for word in text.split(',').map(str::trim) {
+        match word.find('*') {
+            Some(index) => {
+                let value = parse_word(line, word[..index].trim())?;
+                let count = parse_word(line, word[index + 1..].trim())?;
+                if count < 0 {
+                    return Err(PatchError::new(line, format!("negative count in '{}'", word)));
+                }
+                values.extend((0..count).map(|_| value));
+            },
+            None => values.push(parse_word(line, word)?),
+        }
+    }
+
+    Ok(values)
+}
+
+fn parse_pattern(line: usize, text: &str) -> Result<Vec<Option<i64>>, PatchError> {
+    text.split(',')
+        .map(str::trim)
+        .map(|word| match word {
+            "_" => Ok(None),
+            _ => parse_word(line, word).map(Some),
+        })
+        .collect()
+}
+
+fn parse_edit(line: usize, text: &str) -> Result<Edit, PatchError> {
+    if let Some(rest) = text.strip_prefix("replace ") {
+        let (pattern, replacement) = match rest.find("=>") {
+            Some(index) => (&rest[..index], &rest[index + 2..]),
+            None => return Err(PatchError::new(line, String::from("expected '=>'"))),
+        };
+        let pattern = parse_pattern(line, pattern)?;
+        let replacement = parse_pattern(line, replacement)?;
+
+        if pattern.len() != replacement.len() {
+            let message = String::from("replacement must be as long as the pattern");
+            return Err(PatchError::new(line, message));
+        }
+        if pattern.iter().all(Option::is_none) {
+            return Err(PatchError::new(line, String::from("pattern matches everything")));
+        }
+
+        return Ok(Edit::Replace { pattern, replacement });
+    }
+
+    match text.find(':') {
+        Some(index) => {
+            let addr = parse_word(line, text[..index].trim())?;
+            if addr < 0 {
+                return Err(PatchError::new(line, format!("negative address {}", addr)));
+            }
+            let values = parse_values(line, &text[index + 1..])?;
+            Ok(Edit::Write { addr: addr as usize, values })
+        },
+        None => Err(PatchError::new(line, format!("can't parse '{}'", text))),
+    }
+}
+
+impl Patch {
+    pub fn parse(text: &str) -> Result<Patch, PatchError> {
+        let mut edits = vec![];
+
+        for (index, line) in text.lines().enumerate() {
+            let line_no = index + 1;
+            let text = match line.find(';') {
+                Some(comment) => &line[..comment],
+                None => line,
+            }.trim();
+
+            if !text.is_empty() {
+                edits.push((line_no, parse_edit(line_no, text)?));
+            }
+        }
+
+        Ok(Patch { edits })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Patch, Box<dyn Error>> {
+        Ok(Patch::parse(&fs::read_to_string(path)?)?)
+    }
+
+    /// One past the highest address written by an address edit, so a
+    /// patched program can be dumped without losing cells added past its
+    /// original end.
+    pub fn extent(&self) -> usize {
+        self.edits
+            .iter()
+            .map(|(_, edit)| match edit {
+                Edit::Write { addr, values } => addr + values.len(),
+                Edit::Replace { .. } => 0,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl Program {
+    /// Applies the edits of a patch in order.  Replacements search all
+    /// memory up to the last non-zero cell.  Edits before a failing one
+    /// stay applied.
+    pub fn apply_patch(&mut self, patch: &Patch) -> Result<(), PatchError> {
+        for (line, edit) in &patch.edits {
+            match edit {
+                Edit::Write { addr, values } => {
+                    for (offset, &value) in values.iter().enumerate() {
+                        self.write(addr + offset, value);
+                    }
+                },
+                Edit::Replace { pattern, replacement } => {
+                    let end = match self.memory.nonzero_cells().last() {
+                        Some(&(addr, _)) => addr + 1,
+                        None => 0,
+                    };
+
+                    let matches_at = |program: &Program, addr: usize| {
+                        pattern.iter().enumerate().all(|(offset, word)| match word {
+                            Some(word) => program.read(addr + offset) == *word,
+                            None => true,
+                        })
+                    };
+
+                    let mut found = false;
+                    let mut addr = 0;
+                    while addr + pattern.len() <= end {
+                        if !matches_at(self, addr) {
+                            addr += 1;
+                            continue;
+                        }
+
+                        for (offset, word) in replacement.iter().enumerate() {
+                            if let Some(word) = word {
+                                self.write(addr + offset, *word);
+                            }
+                        }
+                        found = true;
+                        addr += pattern.len();
+                    }
+
+                    if !found {
+                        return Err(PatchError::new(*line, String::from("pattern not found")));
+                    }
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patch_is_applied() {
+        let patch = Patch::parse(
+            "
+            ; Comments and blank lines are skipped
+            0: 2
+            8: 7, 0*2 ; repeated values
+            replace 1001, _, 1 => 1001, _, 5
+            ",
+        )
+        .unwrap();
+        assert_eq!(patch.extent(), 11);
+
+        let mut program = Program::new(&[1, 1001, 9, 1, 9, 1001, 4, 1]);
+        program.apply_patch(&patch).unwrap();
+        let memory: Vec<i64> = (0..11).map(|addr| program.read(addr)).collect();
+        assert_eq!(memory, [2, 1001, 9, 5, 9, 1001, 4, 5, 7, 0, 0]);
+    }
+
+    #[test]
+    fn stale_replacement_is_an_error() {
+        let patch = Patch::parse("0: 5\nreplace 3, 4 => 4, 3").unwrap();
+        let mut program = Program::new(&[1, 2]);
+        let err = program.apply_patch(&patch).unwrap_err();
+        assert_eq!(err, PatchError::new(2, String::from("pattern not found")));
+        // Edits before the failing one stay applied
+        assert_eq!(program.read(0), 5);
+    }
+
+    #[test]
+    fn bad_patches_are_rejected() {
+        assert_eq!(Patch::parse("x: 1").err().map(|err| err.line), Some(1));
+        assert!(Patch::parse("\n-1: 1").is_err());
+        assert!(Patch::parse("replace 1, 2 => 3").is_err());
+        assert!(Patch::parse("replace _ => 1").is_err());
+        assert!(Patch::parse("0: 1*-1").is_err());
+    }
+}
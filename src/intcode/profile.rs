@@ -0,0 +1,255 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use super::{Instruction, Program};
+use super::cell::Cell;
+use super::code::Op;
+
+/// Call statistics for a function found by `Profile`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Function {
+    pub calls: u64,
+    /// Instructions executed between entering the function and returning
+    /// from it, including any functions it called
+    pub instructions: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Frame {
+    entry: usize,
+    return_addr: usize,
+    started: u64,
+}
+
+/// Execution counts collected by `Program::step` while profiling is on.
+///
+/// Loops are taken to be backward jumps.  Functions are found from the
+/// calling convention the puzzle programs use: the return address is
+/// written to a relative parameter, and the very next instruction jumps.
+/// A later jump to that return address counts as returning.  Time spent
+/// in recursive calls is only counted once, for the outermost call.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    total: u64,
+    addresses: BTreeMap<usize, u64>,
+    opcodes: BTreeMap<i64, u64>,
+    loops: BTreeMap<(usize, usize), u64>,
+    functions: BTreeMap<usize, Function>,
+    stack: Vec<Frame>,
+    pushed: Option<i64>,
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile::default()
+    }
+
+    /// Records an executed instruction.  `pushed` is the value written if
+    /// the instruction wrote to a relative parameter.
+    pub(super) fn record(
+        &mut self,
+        ip: usize,
+        opcode: i64,
+        len: usize,
+        next_ip: usize,
+        jumped: bool,
+        pushed: Option<i64>,
+    ) {
+        self.total += 1;
+        *self.addresses.entry(ip).or_insert(0) += 1;
+        *self.opcodes.entry(opcode).or_insert(0) += 1;
+
+        if jumped {
+            let return_addr = ip + len;
+
+            if self.pushed == Some(return_addr as i64) {
+                self.functions.entry(next_ip).or_default().calls += 1;
+                self.stack.push(Frame { entry: next_ip, return_addr, started: self.total });
+            } else if let Some(depth) = self.stack.iter().rposition(|f| f.return_addr == next_ip) {
+                let returned: Vec<Frame> = self.stack.drain(depth..).collect();
+                for (i, frame) in returned.iter().enumerate() {
+                    // Recursive calls are already covered by the outermost one
+                    let mut outer = self.stack.iter().chain(&returned[..i]);
+                    if outer.any(|f| f.entry == frame.entry) {
+                        continue;
+                    }
+                    let function = self.functions.get_mut(&frame.entry).unwrap();
+                    function.instructions += self.total - frame.started;
+                }
+            } else if next_ip <= ip {
+                *self.loops.entry((next_ip, ip)).or_insert(0) += 1;
+            }
+        }
+
+        self.pushed = pushed;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn count_at(&self, addr: usize) -> u64 {
+        self.addresses.get(&addr).cloned().unwrap_or(0)
+    }
+
+    /// Instruction counts per address, most executed first.
+    pub fn hot_addresses(&self) -> Vec<(usize, u64)> {
+        let mut counts: Vec<(usize, u64)> = self.addresses
+            .iter()
+            .map(|(&addr, &count)| (addr, count))
+            .collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        counts
+    }
+
+    pub fn opcode_counts(&self) -> &BTreeMap<i64, u64> {
+        &self.opcodes
+    }
+
+    /// Number of times each backward jump was taken, keyed by the jump
+    /// target and the address of the jump.
+    pub fn loops(&self) -> &BTreeMap<(usize, usize), u64> {
+        &self.loops
+    }
+
+    pub fn functions(&self) -> &BTreeMap<usize, Function> {
+        &self.functions
+    }
+
+    /// Formats a report of the `top` most executed addresses, loops and
+    /// functions.  The program is only used to disassemble hot addresses
+    /// and name opcodes, with its own instruction set.
+    pub fn report(&self, program: &Program, top: usize) -> String {
+        let mut report = String::new();
+        let percent = |count: u64| 100.0 * count as f64 / self.total.max(1) as f64;
+
+        writeln!(report, "Total instructions: {}", self.total).unwrap();
+
+        writeln!(report, "\nOpcodes:").unwrap();
+        let mut opcodes: Vec<(i64, u64)> = self.opcodes.iter().map(|(&op, &count)| (op, count)).collect();
+        opcodes.sort_by_key(|&(_, count)| Reverse(count));
+        for (opcode, count) in opcodes {
+            let name = program.mnemonic(opcode);
+            writeln!(report, "  {:<4} {:>12} {:>6.2}%", name, count, percent(count)).unwrap();
+        }
+
+        writeln!(report, "\nHot addresses:").unwrap();
+        for (addr, count) in self.hot_addresses().into_iter().take(top) {
+            let text = program.instruction_text(addr).unwrap_or_else(|| String::from("???"));
+            writeln!(report, "  {:>5}: {:<32} {:>12} {:>6.2}%", addr, text, count, percent(count)).unwrap();
+        }
+
+        writeln!(report, "\nLoops:").unwrap();
+        let mut loops: Vec<(usize, usize, u64, u64)> = self.loops
+            .iter()
+            .map(|(&(start, end), &iterations)| {
+                let executed = self.addresses.range(start..=end).map(|(_, count)| count).sum();
+                (start, end, iterations, executed)
+            })
+            .collect();
+        loops.sort_by_key(|&(_, _, _, executed)| Reverse(executed));
+        for (start, end, iterations, executed) in loops.into_iter().take(top) {
+            writeln!(
+                report,
+                "  {:>5}..{:<5} {:>10} iterations {:>12} instructions {:>6.2}%",
+                start, end, iterations, executed, percent(executed),
+            ).unwrap();
+        }
+
+        writeln!(report, "\nFunctions:").unwrap();
+        let mut functions: Vec<(&usize, &Function)> = self.functions.iter().collect();
+        functions.sort_by_key(|(_, function)| Reverse(function.instructions));
+        for (entry, function) in functions.into_iter().take(top) {
+            writeln!(
+                report,
+                "  {:>5}       {:>10} calls      {:>12} instructions {:>6.2}%",
+                entry, function.calls, function.instructions, percent(function.instructions),
+            ).unwrap();
+        }
+
+        report
+    }
+}
+
+impl<T: Cell> Program<T> {
+    fn mnemonic(&self, opcode: i64) -> &str {
+        match &self.isa {
+            Some(isa) => isa.mnemonic(opcode).unwrap_or("???"),
+            None => Instruction::new(opcode).mnemonic(),
+        }
+    }
+
+    /// Disassembles the instruction at `addr` with the program's
+    /// instruction set.
+    fn instruction_text(&self, addr: usize) -> Option<String> {
+        let op = Op::decode(addr, |opcode| self.shape(opcode), |addr| self.read(addr).to_i64());
+        let op = op.ok()?;
+        Some(op.instruction().text(self.mnemonic(op.opcode), op.writes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{Param, VmError};
+    use crate::intcode::isa::{Extension, InstructionSet};
+
+    struct Nop;
+
+    impl Extension for Nop {
+        fn mnemonic(&self) -> &str {
+            "NOP"
+        }
+
+        fn param_count(&self) -> usize {
+            1
+        }
+
+        fn execute(&self, _program: &mut Program, _params: &[Param]) -> Result<(), VmError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn far_addresses_are_counted() {
+        // JT #1, #far, with a HLT there
+        let far: i64 = 1 << 40;
+        let mut program = Program::new(&[1105, 1, far]);
+        program.store(far as usize, 99);
+        program.start_profiling();
+        program.run().unwrap();
+
+        let profile = program.stop_profiling().unwrap();
+        assert_eq!(profile.total(), 2);
+        assert_eq!(profile.count_at(far as usize), 1);
+        assert_eq!(profile.hot_addresses(), [(0, 1), (far as usize, 1)]);
+    }
+
+    #[test]
+    fn long_loops_are_reported() {
+        let far = 1 << 40;
+        let mut profile = Profile::new();
+        profile.record(0, 1, 4, 4, false, None);
+        profile.record(far, 5, 3, 0, true, None);
+        assert_eq!(profile.loops()[&(0, far)], 1);
+
+        let report = profile.report(&Program::new(&[99]), 10);
+        assert!(report.contains(&format!("0..{} ", far)));
+    }
+
+    #[test]
+    fn report_uses_the_instruction_set() {
+        let mut isa = InstructionSet::standard();
+        isa.register(42, Nop);
+        // NOP #5; HLT
+        let mut program = Program::new(&[142, 5, 99]);
+        program.set_instruction_set(isa);
+        program.start_profiling();
+        program.run().unwrap();
+
+        let report = program.profile().unwrap().report(&program, 10);
+        assert!(report.contains("  NOP "));
+        assert!(report.contains("NOP #5"));
+        assert!(!report.contains("???"));
+    }
+}
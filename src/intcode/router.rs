@@ -0,0 +1,308 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use super::{Program, RunState, VmError};
+
+/// An `(x, y)` packet sent from one address to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Packet {
+    pub src: i64,
+    pub dst: i64,
+    pub x: i64,
+    pub y: i64,
+}
+
+/// What a handler wants done with a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Hand the packet on to the next handler, or to the node at the
+    /// destination address
+    Pass,
+    /// The handler took care of the packet
+    Consume,
+    Drop,
+}
+
+/// What happened to a packet, as recorded in the packet log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fate {
+    Delivered,
+    Consumed,
+    Dropped,
+}
+
+impl fmt::Display for Fate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Fate::Delivered => "delivered",
+            Fate::Consumed => "consumed",
+            Fate::Dropped => "dropped",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogEntry {
+    /// The round in which the packet was sent
+    pub round: u64,
+    pub packet: Packet,
+    pub fate: Fate,
+}
+
+impl LogEntry {
+    /// Formats the entry as a single line of JSON.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"round\":{},\"src\":{},\"dst\":{},\"x\":{},\"y\":{},\"fate\":\"{}\"}}",
+            self.round,
+            self.packet.src,
+            self.packet.dst,
+            self.packet.x,
+            self.packet.y,
+            self.fate,
+        )
+    }
+}
+
+/// Gets to see every packet sent to the address it's attached to.
+pub trait Handler: Send {
+    fn packet(&mut self, packet: &Packet) -> Verdict;
+
+    /// Called by `Network::wake` once the network has gone idle.  Any
+    /// packets returned are sent on the handler's behalf.
+    fn idle(&mut self, _address: i64) -> Vec<Packet> {
+        vec![]
+    }
+}
+
+/// Keeps the last packet sent to it, and sends it to address 0 whenever
+/// the network goes idle.
+#[derive(Debug, Default)]
+pub struct Nat {
+    received: Vec<Packet>,
+    sent: Vec<Packet>,
+}
+
+impl Nat {
+    pub fn new() -> Nat {
+        Nat::default()
+    }
+
+    /// All packets sent to the NAT, oldest first.
+    pub fn received(&self) -> &[Packet] {
+        &self.received
+    }
+
+    /// All packets the NAT has sent to wake the network, oldest first.
+    pub fn sent(&self) -> &[Packet] {
+        &self.sent
+    }
+}
+
+impl Handler for Nat {
+    fn packet(&mut self, packet: &Packet) -> Verdict {
+        self.received.push(*packet);
+        Verdict::Consume
+    }
+
+    fn idle(&mut self, address: i64) -> Vec<Packet> {
+        match self.received.last() {
+            Some(last) => {
+                let packet = Packet { src: address, dst: 0, x: last.x, y: last.y };
+                self.sent.push(packet);
+                vec![packet]
+            },
+            None => vec![],
+        }
+    }
+}
+
+/// Writes every packet passing through as a line of text, and lets it
+/// through.
+pub struct Logger<W: Write + Send> {
+    writer: W,
+}
+
+impl<W: Write + Send> Logger<W> {
+    pub fn new(writer: W) -> Logger<W> {
+        Logger { writer }
+    }
+}
+
+impl<W: Write + Send> Handler for Logger<W> {
+    fn packet(&mut self, packet: &Packet) -> Verdict {
+        let _ = writeln!(self.writer, "{} -> {}: ({}, {})", packet.src, packet.dst, packet.x, packet.y);
+        Verdict::Pass
+    }
+}
+
+/// Drops every packet matching a predicate.
+pub struct DropFilter {
+    predicate: Box<dyn FnMut(&Packet) -> bool + Send>,
+}
+
+impl DropFilter {
+    pub fn new<F: FnMut(&Packet) -> bool + Send + 'static>(predicate: F) -> DropFilter {
+        DropFilter { predicate: Box::new(predicate) }
+    }
+}
+
+impl Handler for DropFilter {
+    fn packet(&mut self, packet: &Packet) -> Verdict {
+        if (self.predicate)(packet) {
+            Verdict::Drop
+        } else {
+            Verdict::Pass
+        }
+    }
+}
+
+/// A network of programs that talk to each other in packets, like the
+/// ones on day 23.
+///
+/// Each node is booted with its address as the first input.  A node sends
+/// a packet by writing the destination address followed by `x` and `y`,
+/// and reads `-1` when there's nothing waiting for it.  Packets go through
+/// the handlers attached to the destination address in the order they
+/// were added, and on to the node at that address if none of them
+/// consumes or drops it.  Packets to addresses without a node are dropped.
+pub struct Network {
+    nodes: Vec<Program>,
+    queues: Vec<VecDeque<(i64, i64)>>,
+    handlers: BTreeMap<i64, Vec<Arc<Mutex<dyn Handler>>>>,
+    log: Vec<LogEntry>,
+    round: u64,
+}
+
+impl Network {
+    pub fn new(memory: &[i64], size: usize) -> Network {
+        let nodes = (0..size)
+            .map(|addr| {
+                let mut node = Program::new(memory);
+                node.set_input(addr as i64);
+                node
+            })
+            .collect();
+
+        Network {
+            nodes,
+            queues: vec![VecDeque::new(); size],
+            handlers: BTreeMap::new(),
+            log: vec![],
+            round: 0,
+        }
+    }
+
+    pub fn add_handler(&mut self, address: i64, handler: Arc<Mutex<dyn Handler>>) {
+        self.handlers.entry(address).or_default().push(handler);
+    }
+
+    pub fn node(&self, address: usize) -> &Program {
+        &self.nodes[address]
+    }
+
+    /// Number of rounds run so far.
+    pub fn round(&self) -> u64 {
+        self.round
+    }
+
+    pub fn log(&self) -> &[LogEntry] {
+        &self.log
+    }
+
+    /// Writes the packet log as JSON lines.
+    pub fn write_log<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for entry in &self.log {
+            writeln!(writer, "{}", entry.to_json())?;
+        }
+        writer.flush()
+    }
+
+    /// Sends a packet into the network from outside.
+    pub fn send(&mut self, packet: Packet) {
+        let mut fate = None;
+
+        if let Some(handlers) = self.handlers.get(&packet.dst) {
+            for handler in handlers {
+                match handler.lock().unwrap().packet(&packet) {
+                    Verdict::Pass => (),
+                    Verdict::Consume => fate = Some(Fate::Consumed),
+                    Verdict::Drop => fate = Some(Fate::Dropped),
+                }
+                if fate.is_some() {
+                    break;
+                }
+            }
+        }
+
+        let fate = match fate {
+            Some(fate) => fate,
+            None if packet.dst >= 0 && (packet.dst as usize) < self.nodes.len() => {
+                self.queues[packet.dst as usize].push_back((packet.x, packet.y));
+                Fate::Delivered
+            },
+            None => Fate::Dropped,
+        };
+
+        self.log.push(LogEntry { round: self.round, packet, fate });
+    }
+
+    /// Gives every node a turn, running it until it halts or asks for
+    /// input that isn't there.  Returns true if the network was idle,
+    /// meaning no node sent or received anything during the round.
+    pub fn step(&mut self) -> Result<bool, VmError> {
+        let mut idle = true;
+
+        for addr in 0..self.nodes.len() {
+            loop {
+                match self.nodes[addr].run_until_blocked()? {
+                    RunState::Output(dst) => {
+                        if let [x, y] = self.nodes[addr].run_until_output_count(2)?[..] {
+                            self.send(Packet { src: addr as i64, dst, x, y });
+                        }
+                        idle = false;
+                    },
+                    RunState::NeedsInput => match self.queues[addr].pop_front() {
+                        Some((x, y)) => {
+                            self.nodes[addr].push_inputs(vec![x, y]);
+                            idle = false;
+                        },
+                        None => {
+                            self.nodes[addr].set_input(-1);
+                            break;
+                        },
+                    },
+                    RunState::Halted => break,
+                }
+            }
+        }
+
+        self.round += 1;
+        Ok(idle)
+    }
+
+    /// Runs rounds until one of them is idle.
+    pub fn run_until_idle(&mut self) -> Result<(), VmError> {
+        while !self.step()? {}
+        Ok(())
+    }
+
+    /// Lets every handler send packets to get an idle network going
+    /// again, and returns the packets that were sent.
+    pub fn wake(&mut self) -> Vec<Packet> {
+        let mut packets = vec![];
+        for (&address, handlers) in &self.handlers {
+            for handler in handlers {
+                packets.extend(handler.lock().unwrap().idle(address));
+            }
+        }
+
+        for &packet in &packets {
+            self.send(packet);
+        }
+
+        packets
+    }
+}
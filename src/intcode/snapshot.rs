@@ -0,0 +1,209 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use super::Program;
+use super::cell::Cell;
+use super::memory::Memory;
+
+const HEADER: &str = "intcode-snapshot";
+const VERSION: u32 = 2;
+const MAX_ZERO_RUN: usize = 16;
+
+fn invalid_data(line: usize, message: &str) -> io::Error {
+    let message = format!("snapshot line {}: {}", line, message);
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn format_values<T: Cell>(values: &[T]) -> String {
+    let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    values.join(",")
+}
+
+fn parse_values<T: FromStr>(line: usize, text: &str) -> io::Result<Vec<T>> {
+    if text.is_empty() {
+        return Ok(vec![]);
+    }
+
+    text.split(',')
+        .map(|value| value.parse().map_err(|_| invalid_data(line, "invalid value")))
+        .collect()
+}
+
+fn parse_number<T: FromStr>(line: usize, text: &str) -> io::Result<T> {
+    text.parse().map_err(|_| invalid_data(line, "invalid number"))
+}
+
+impl<T: Cell> Program<T> {
+    /// Writes the complete state of the program in a line based text
+    /// format:
+    ///
+    /// ```text
+    /// intcode-snapshot 2
+    /// ip 12
+    /// relative_base 2656
+    /// halted 0
+    /// checked 1
+    /// instruction_count 345
+    /// inputs 1,2
+    /// outputs 17
+    /// memory 0 1,380,379,385
+    /// memory 2656 7
+    /// ```
+    ///
+    /// Inputs and outputs are listed oldest first, and memory is stored as
+    /// runs of cells, leaving out long stretches of zeroes.  Tracers aren't
+    /// part of the snapshot, and neither are the instruction set, host
+    /// calls and devices.
+    pub fn save_snapshot<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{} {}", HEADER, VERSION)?;
+        writeln!(writer, "ip {}", self.ip)?;
+        writeln!(writer, "relative_base {}", self.relative_base)?;
+        writeln!(writer, "halted {}", self.halted as u8)?;
+        writeln!(writer, "checked {}", self.checked as u8)?;
+        writeln!(writer, "instruction_count {}", self.instruction_count)?;
+        writeln!(writer, "inputs {}", format_values(&self.pending_inputs()))?;
+        let outputs: Vec<T> = self.outputs.iter().cloned().collect();
+        writeln!(writer, "outputs {}", format_values(&outputs))?;
+
+        let addrs: Vec<usize> = self.memory
+            .nonzero_cells()
+            .iter()
+            .map(|&(addr, _)| addr)
+            .collect();
+
+        let mut run_start = 0;
+        while run_start < addrs.len() {
+            let mut run_end = run_start + 1;
+            while run_end < addrs.len() && addrs[run_end] - addrs[run_end - 1] <= MAX_ZERO_RUN {
+                run_end += 1;
+            }
+
+            let values: Vec<T> = (addrs[run_start]..=addrs[run_end - 1])
+                .map(|addr| self.read(addr))
+                .collect();
+            writeln!(writer, "memory {} {}", addrs[run_start], format_values(&values))?;
+            run_start = run_end;
+        }
+
+        writer.flush()
+    }
+
+    pub fn save_snapshot_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.save_snapshot(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<T: Cell + FromStr> Program<T> {
+    /// Restores a program from a snapshot written by `save_snapshot`.  It
+    /// runs with the standard instruction set and no host calls or
+    /// devices, so a program that used any has to have them set up again.
+    pub fn load_snapshot<R: BufRead>(reader: R) -> io::Result<Program<T>> {
+        let mut program = Program::new(&[]);
+        let mut memory = Memory::default();
+        let mut lines = reader.lines().enumerate();
+
+        match lines.next() {
+            Some((_, header)) => {
+                let header = header?;
+                let version = match header.split_whitespace().collect::<Vec<_>>()[..] {
+                    [HEADER, version] => parse_number::<u32>(1, version)?,
+                    _ => return Err(invalid_data(1, "not an intcode snapshot")),
+                };
+                if version != VERSION {
+                    return Err(invalid_data(1, "unsupported snapshot version"));
+                }
+            },
+            None => return Err(invalid_data(1, "empty snapshot")),
+        }
+
+        for (index, line) in lines {
+            let line = line?;
+            let line_no = index + 1;
+            let mut parts = line.trim().splitn(2, ' ');
+            let key = parts.next().unwrap_or("");
+            let value = parts.next().unwrap_or("").trim();
+
+            match key {
+                "ip" => program.ip = parse_number(line_no, value)?,
+                "relative_base" => program.relative_base = parse_number(line_no, value)?,
+                "halted" => program.halted = parse_number::<u8>(line_no, value)? != 0,
+                "checked" => program.checked = parse_number::<u8>(line_no, value)? != 0,
+                "instruction_count" => {
+                    program.instruction_count = parse_number(line_no, value)?;
+                },
+                "inputs" => program.push_inputs(parse_values(line_no, value)?),
+                "outputs" => program.outputs = parse_values(line_no, value)?.into(),
+                "memory" => {
+                    let mut run = value.splitn(2, ' ');
+                    let start: usize = parse_number(line_no, run.next().unwrap_or(""))?;
+                    let values = parse_values(line_no, run.next().unwrap_or("").trim())?;
+                    for (offset, value) in values.into_iter().enumerate() {
+                        memory.write(start + offset, value);
+                    }
+                },
+                "" => (),
+                _ => return Err(invalid_data(line_no, "unknown field")),
+            }
+        }
+
+        program.memory = memory;
+        Ok(program)
+    }
+
+    pub fn load_snapshot_from_file<P: AsRef<Path>>(path: P) -> io::Result<Program<T>> {
+        Program::load_snapshot(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::BigInt;
+
+    fn round_trip<T: Cell + FromStr>(program: &Program<T>) -> Program<T> {
+        let mut snapshot = vec![];
+        program.save_snapshot(&mut snapshot).unwrap();
+        Program::load_snapshot(&snapshot[..]).unwrap()
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        // IN -> [100]; OUT [100]; ARB #7; HLT, with a cell far out
+        let mut program: Program = Program::new(&[3, 100, 4, 100, 109, 7, 99]);
+        program.store(5000, -3);
+        program.push_inputs(vec![42, 43]);
+        program.set_checked(true);
+        program.step().unwrap();
+        program.step().unwrap();
+        program.step().unwrap();
+
+        let mut restored = round_trip(&program);
+        assert!(restored.is_checked());
+        assert_eq!(restored.ip, 6);
+        assert_eq!(restored.relative_base, 7);
+        assert_eq!(restored.instruction_count, 3);
+        assert_eq!(restored.pending_inputs(), [43]);
+        assert_eq!(restored.read(100), 42);
+        assert_eq!(restored.read(5000), -3);
+        assert_eq!(restored.drain_outputs(), [42]);
+        restored.run().unwrap();
+        assert!(restored.halted);
+    }
+
+    #[test]
+    fn bigint_snapshot_round_trip() {
+        let big = BigInt::from(i64::MAX) * BigInt::from(i64::MAX);
+        let mut program = Program::new(&[BigInt::from(99)]);
+        program.store(10, big.clone());
+
+        let restored = round_trip(&program);
+        assert_eq!(restored.read(10), big);
+    }
+
+    #[test]
+    fn bad_snapshots_are_rejected() {
+        assert!(Program::<i64>::load_snapshot(&b"intcode-snapshot 1\n"[..]).is_err());
+        assert!(Program::<i64>::load_snapshot(&b"intcode-snapshot 2\nip x\n"[..]).is_err());
+    }
+}
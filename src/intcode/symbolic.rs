@@ -0,0 +1,785 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::ops::{Add, Mul, Range};
+use std::sync::Arc;
+use super::{Program, VmError};
+use super::isa::{Builtin, InstructionSet, Shape};
+use super::memory::Memory;
+
+const DEFAULT_MAX_PATHS: usize = 256;
+const DEFAULT_MAX_STEPS: u64 = 100_000;
+
+/// Something an expression depends on that isn't a plain number.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Atom {
+    Symbol(String),
+    /// The result of a less-than comparison, which is 0 or 1
+    Lt(Box<Expr>, Box<Expr>),
+    /// The result of an equals comparison, which is 0 or 1
+    Eq(Box<Expr>, Box<Expr>),
+    /// A read through a symbolic address
+    Load(Box<Expr>),
+}
+
+impl fmt::Display for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Atom::Symbol(name) => write!(f, "{}", name),
+            Atom::Lt(a, b) => write!(f, "({} < {})", a, b),
+            Atom::Eq(a, b) => write!(f, "({} == {})", a, b),
+            Atom::Load(addr) => write!(f, "[{}]", addr),
+        }
+    }
+}
+
+/// A polynomial with integer coefficients over symbols and other atoms.
+///
+/// Adding and multiplying expressions keeps them in a normal form, so
+/// `(noun + 1) * 2 - noun` comes out as `noun + 2` and an expression that
+/// doesn't depend on any symbols is always a constant.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Expr {
+    /// Coefficients by monomial, which is a sorted list of atoms.  The
+    /// empty monomial is the constant term.  There are no zero
+    /// coefficients.
+    terms: BTreeMap<Vec<Atom>, i64>,
+}
+
+impl Expr {
+    pub fn constant(value: i64) -> Expr {
+        let mut terms = BTreeMap::new();
+        if value != 0 {
+            terms.insert(vec![], value);
+        }
+        Expr { terms }
+    }
+
+    pub fn symbol(name: &str) -> Expr {
+        Expr::atom(Atom::Symbol(String::from(name)))
+    }
+
+    fn atom(atom: Atom) -> Expr {
+        let mut terms = BTreeMap::new();
+        terms.insert(vec![atom], 1);
+        Expr { terms }
+    }
+
+    /// `1` if `a < b` and `0` otherwise.
+    pub fn lt(a: Expr, b: Expr) -> Expr {
+        if let (Some(a), Some(b)) = (a.as_constant(), b.as_constant()) {
+            return Expr::constant((a < b) as i64);
+        }
+        match (a.clone() + b.clone() * Expr::constant(-1)).as_constant() {
+            Some(diff) => Expr::constant((diff < 0) as i64),
+            None => Expr::atom(Atom::Lt(Box::new(a), Box::new(b))),
+        }
+    }
+
+    /// `1` if `a == b` and `0` otherwise.
+    pub fn eq(a: Expr, b: Expr) -> Expr {
+        match (a.clone() + b.clone() * Expr::constant(-1)).as_constant() {
+            Some(diff) => Expr::constant((diff == 0) as i64),
+            None => Expr::atom(Atom::Eq(Box::new(a), Box::new(b))),
+        }
+    }
+
+    pub fn as_constant(&self) -> Option<i64> {
+        match self.terms.iter().next() {
+            None => Some(0),
+            Some((monomial, &coefficient)) if monomial.is_empty() && self.terms.len() == 1 => {
+                Some(coefficient)
+            },
+            _ => None,
+        }
+    }
+
+    /// Names of the symbols the expression depends on, including through
+    /// comparisons and loads.
+    pub fn symbols(&self) -> BTreeSet<String> {
+        let mut symbols = BTreeSet::new();
+
+        for atom in self.terms.keys().flatten() {
+            match atom {
+                Atom::Symbol(name) => {
+                    symbols.insert(name.clone());
+                },
+                Atom::Lt(a, b) | Atom::Eq(a, b) => {
+                    symbols.extend(a.symbols());
+                    symbols.extend(b.symbols());
+                },
+                Atom::Load(addr) => symbols.extend(addr.symbols()),
+            }
+        }
+
+        symbols
+    }
+
+    /// Evaluates the expression with the given symbol values.  Returns
+    /// `None` if a symbol has no value, the expression reads through a
+    /// symbolic address, since memory isn't known anymore, or the result
+    /// overflows.
+    pub fn eval(&self, values: &HashMap<String, i64>) -> Option<i64> {
+        let mut sum: i64 = 0;
+
+        for (monomial, coefficient) in &self.terms {
+            let mut product = *coefficient;
+            for atom in monomial {
+                product = product.checked_mul(match atom {
+                    Atom::Symbol(name) => *values.get(name)?,
+                    Atom::Lt(a, b) => (a.eval(values)? < b.eval(values)?) as i64,
+                    Atom::Eq(a, b) => (a.eval(values)? == b.eval(values)?) as i64,
+                    Atom::Load(_) => return None,
+                })?;
+            }
+            sum = sum.checked_add(product)?;
+        }
+
+        Some(sum)
+    }
+
+    /// The constant term and the coefficient of each symbol, if the
+    /// expression is a linear function of symbols.
+    pub fn linear(&self) -> Option<(i64, BTreeMap<String, i64>)> {
+        let mut constant = 0;
+        let mut coefficients = BTreeMap::new();
+
+        for (monomial, &coefficient) in &self.terms {
+            match &monomial[..] {
+                [] => constant = coefficient,
+                [Atom::Symbol(name)] => {
+                    coefficients.insert(name.clone(), coefficient);
+                },
+                _ => return None,
+            }
+        }
+
+        Some((constant, coefficients))
+    }
+
+    /// Finds values for symbols within the given bounds that make a linear
+    /// expression equal `target`, returning them in the order of `bounds`.
+    /// All but the last symbol are searched, and the last one is solved
+    /// for, so put the symbol with the widest range last.  Returns `None`
+    /// if there's no solution, the expression isn't linear, or it depends
+    /// on symbols that aren't bounded.  Values for which the expression
+    /// overflows aren't solutions.
+    pub fn solve(&self, target: i64, bounds: &[(&str, Range<i64>)]) -> Option<Vec<i64>> {
+        let (constant, coefficients) = self.linear()?;
+        if coefficients.keys().any(|name| bounds.iter().all(|(bound, _)| bound != name)) {
+            return None;
+        }
+
+        let coefficient = |name: &str| coefficients.get(name).cloned().unwrap_or(0);
+        let ((last, last_range), searched) = bounds.split_last()?;
+        let mut values: Vec<i64> = searched.iter().map(|(_, range)| range.start).collect();
+        if searched.iter().any(|(_, range)| range.is_empty()) {
+            return None;
+        }
+
+        loop {
+            let rest = searched
+                .iter()
+                .zip(&values)
+                .try_fold(target.checked_sub(constant)?, |rest, ((name, _), &value)| {
+                    rest.checked_sub(coefficient(name).checked_mul(value)?)
+                });
+
+            let last_value = match (coefficient(last), rest) {
+                (_, None) => None,
+                (0, Some(0)) => Some(last_range.start).filter(|_| !last_range.is_empty()),
+                (0, Some(_)) => None,
+                (c, Some(rest)) if rest.checked_rem(c) == Some(0) => {
+                    rest.checked_div(c).filter(|value| last_range.contains(value))
+                },
+                _ => None,
+            };
+            if let Some(last_value) = last_value {
+                values.push(last_value);
+                return Some(values);
+            }
+
+            // Next combination of the searched symbols
+            let mut i = 0;
+            loop {
+                if i == values.len() {
+                    return None;
+                }
+                values[i] += 1;
+                if values[i] < searched[i].1.end {
+                    break;
+                }
+                values[i] = searched[i].1.start;
+                i += 1;
+            }
+        }
+    }
+
+    /// Replaces atoms with known values.
+    fn substitute(&self, known: &BTreeMap<Atom, i64>) -> Expr {
+        if known.is_empty() {
+            return self.clone();
+        }
+
+        let mut result = Expr::default();
+        for (monomial, &coefficient) in &self.terms {
+            let mut term = Expr::constant(coefficient);
+            for atom in monomial {
+                term = term * match known.get(atom) {
+                    Some(&value) => Expr::constant(value),
+                    None => Expr::atom(atom.clone()),
+                };
+            }
+            result = result + term;
+        }
+        result
+    }
+
+    /// Adds `coefficient` times `monomial`, wrapping on overflow.
+    fn add_term(&mut self, monomial: Vec<Atom>, coefficient: i64) {
+        let sum = self.terms.get(&monomial).cloned().unwrap_or(0).wrapping_add(coefficient);
+        if sum == 0 {
+            self.terms.remove(&monomial);
+        } else {
+            self.terms.insert(monomial, sum);
+        }
+    }
+
+    /// The atom and its coefficient, if the expression is a multiple of a
+    /// single atom.
+    fn single_atom(&self) -> Option<(&Atom, i64)> {
+        match self.terms.iter().next() {
+            Some((monomial, &coefficient)) if monomial.len() == 1 && self.terms.len() == 1 => {
+                Some((&monomial[0], coefficient))
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Add for Expr {
+    type Output = Expr;
+
+    fn add(mut self, other: Expr) -> Expr {
+        for (monomial, coefficient) in other.terms {
+            self.add_term(monomial, coefficient);
+        }
+        self
+    }
+}
+
+impl Mul for Expr {
+    type Output = Expr;
+
+    fn mul(self, other: Expr) -> Expr {
+        let mut product = Expr::default();
+
+        for (a, ca) in &self.terms {
+            for (b, cb) in &other.terms {
+                let mut monomial: Vec<Atom> = a.iter().chain(b).cloned().collect();
+                monomial.sort();
+                product.add_term(monomial, ca.wrapping_mul(*cb));
+            }
+        }
+
+        product
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.terms.is_empty() {
+            return write!(f, "0");
+        }
+
+        // Constant term last, like people write it
+        let terms = self.terms
+            .iter()
+            .filter(|(monomial, _)| !monomial.is_empty())
+            .chain(self.terms.iter().filter(|(monomial, _)| monomial.is_empty()));
+
+        for (i, (monomial, &coefficient)) in terms.enumerate() {
+            match (i, coefficient < 0) {
+                (0, true) => write!(f, "-")?,
+                (0, false) => (),
+                (_, true) => write!(f, " - ")?,
+                (_, false) => write!(f, " + ")?,
+            }
+            let magnitude = coefficient.unsigned_abs();
+
+            let atoms: Vec<String> = monomial.iter().map(Atom::to_string).collect();
+            match (magnitude, atoms.is_empty()) {
+                (_, true) => write!(f, "{}", magnitude)?,
+                (1, false) => write!(f, "{}", atoms.join("*"))?,
+                (_, false) => write!(f, "{}*{}", magnitude, atoms.join("*"))?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A branch decision on a path: `expr` was non-zero if `holds`, and zero
+/// otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constraint {
+    pub expr: Expr,
+    pub holds: bool,
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.expr.single_atom(), self.holds) {
+            (Some((Atom::Lt(a, b), 1)), true) => write!(f, "{} < {}", a, b),
+            (Some((Atom::Lt(a, b), 1)), false) => write!(f, "{} >= {}", a, b),
+            (Some((Atom::Eq(a, b), 1)), true) => write!(f, "{} == {}", a, b),
+            (Some((Atom::Eq(a, b), 1)), false) => write!(f, "{} != {}", a, b),
+            (_, true) => write!(f, "{} != 0", self.expr),
+            (_, false) => write!(f, "{} == 0", self.expr),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SymbolicError {
+    Vm(VmError),
+    /// An opcode or parameter mode depends on a symbol
+    SymbolicInstruction { ip: usize },
+    /// A write, jump or relative base change goes to an address that
+    /// depends on a symbol
+    SymbolicAddress { ip: usize },
+    /// An extension instruction, which can only be run by the VM
+    Extension { ip: usize },
+    /// Branches on symbols split execution into more paths than allowed
+    PathLimit,
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolicError::Vm(error) => write!(f, "{}", error),
+            SymbolicError::SymbolicInstruction { ip } => {
+                write!(f, "symbolic instruction at ip {}", ip)
+            },
+            SymbolicError::SymbolicAddress { ip } => write!(f, "symbolic address at ip {}", ip),
+            SymbolicError::Extension { ip } => write!(f, "extension instruction at ip {}", ip),
+            SymbolicError::PathLimit => write!(f, "too many paths"),
+        }
+    }
+}
+
+impl Error for SymbolicError {}
+
+/// Why a path stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum End {
+    Halted,
+    /// The program wants input that wasn't given
+    NeedsInput,
+    StepLimit,
+}
+
+/// One way execution can go, depending on the symbols.
+#[derive(Clone)]
+pub struct Path {
+    /// The branch decisions that lead down this path, in order
+    pub constraints: Vec<Constraint>,
+    pub outputs: Vec<Expr>,
+    pub end: End,
+    pub ip: usize,
+    memory: Memory,
+    cells: BTreeMap<usize, Expr>,
+}
+
+impl Path {
+    pub fn read(&self, addr: usize) -> Expr {
+        match self.cells.get(&addr) {
+            Some(value) => value.clone(),
+            None => Expr::constant(self.memory.read(addr)),
+        }
+    }
+
+    /// Whether the path is taken with the given symbol values.  Returns
+    /// `None` if that can't be worked out.
+    pub fn is_taken(&self, values: &HashMap<String, i64>) -> Option<bool> {
+        for constraint in &self.constraints {
+            if (constraint.expr.eval(values)? != 0) != constraint.holds {
+                return Some(false);
+            }
+        }
+        Some(true)
+    }
+}
+
+#[derive(Clone)]
+struct State {
+    ip: usize,
+    relative_base: i64,
+    /// Cells that were written or made symbolic, over the initial memory
+    cells: BTreeMap<usize, Expr>,
+    inputs: VecDeque<Expr>,
+    outputs: Vec<Expr>,
+    constraints: Vec<Constraint>,
+    /// Values of atoms implied by the constraints
+    known: BTreeMap<Atom, i64>,
+    steps: u64,
+}
+
+impl State {
+    fn constrain(&mut self, expr: Expr, holds: bool) {
+        if let Some((atom, coefficient)) = expr.single_atom() {
+            match (atom, holds) {
+                (_, false) => {
+                    self.known.insert(atom.clone(), 0);
+                },
+                (Atom::Lt(..), true) | (Atom::Eq(..), true) if coefficient != 0 => {
+                    self.known.insert(atom.clone(), 1);
+                },
+                _ => (),
+            }
+        }
+        self.constraints.push(Constraint { expr, holds });
+    }
+}
+
+enum Stepped {
+    Running,
+    Forked(State),
+    Stopped(End),
+}
+
+/// A program with some of its memory or inputs replaced by symbols, run
+/// along every path the symbols can send it down.
+///
+/// Values are kept as `Expr`s, so outputs and memory come out as formulas
+/// in the symbols.  When a jump depends on a symbol, execution forks and
+/// each path records the branch it took.  Comparisons a path has already
+/// branched on are known from then on, so loops testing the same condition
+/// don't fork again.  Reads through symbolic addresses give opaque values,
+/// but writes and jumps through them can't be followed.  Arithmetic wraps,
+/// as it does for a program that isn't checked.
+///
+/// Opcodes are decoded with the program's instruction set, but only
+/// builtins can be run; reaching an extension is an error.
+pub struct Symbolic {
+    memory: Memory,
+    isa: Option<Arc<InstructionSet>>,
+    state: State,
+    max_paths: usize,
+    max_steps: u64,
+}
+
+impl Symbolic {
+    /// Makes the cell at `addr` a symbol with the given name.
+    pub fn set_symbol(&mut self, addr: usize, name: &str) {
+        self.set(addr, Expr::symbol(name));
+    }
+
+    pub fn set(&mut self, addr: usize, value: Expr) {
+        self.state.cells.insert(addr, value);
+    }
+
+    pub fn push_input(&mut self, value: Expr) {
+        self.state.inputs.push_back(value);
+    }
+
+    /// Adds an input that's a symbol with the given name.
+    pub fn push_symbol(&mut self, name: &str) {
+        self.push_input(Expr::symbol(name));
+    }
+
+    pub fn set_max_paths(&mut self, max_paths: usize) {
+        self.max_paths = max_paths;
+    }
+
+    /// Sets how many instructions a single path may run for.
+    pub fn set_max_steps(&mut self, max_steps: u64) {
+        self.max_steps = max_steps;
+    }
+
+    /// Runs until every path has halted, run out of input or hit the step
+    /// limit.
+    pub fn run(&self) -> Result<Vec<Path>, SymbolicError> {
+        let mut paths = vec![];
+        let mut pending = vec![self.state.clone()];
+
+        while let Some(mut state) = pending.pop() {
+            let end = loop {
+                if state.steps >= self.max_steps {
+                    break End::StepLimit;
+                }
+                match self.step(&mut state)? {
+                    Stepped::Running => (),
+                    Stepped::Forked(other) => {
+                        if paths.len() + pending.len() + 2 > self.max_paths {
+                            return Err(SymbolicError::PathLimit);
+                        }
+                        pending.push(other);
+                    },
+                    Stepped::Stopped(end) => break end,
+                }
+            };
+
+            paths.push(Path {
+                constraints: state.constraints,
+                outputs: state.outputs,
+                end,
+                ip: state.ip,
+                memory: self.memory.clone(),
+                cells: state.cells,
+            });
+        }
+
+        Ok(paths)
+    }
+
+    fn word(&self, state: &State, addr: usize) -> Expr {
+        match state.cells.get(&addr) {
+            Some(value) => value.clone(),
+            None => Expr::constant(self.memory.read(addr)),
+        }
+    }
+
+    /// Resolves a parameter to an address, if it doesn't depend on a
+    /// symbol.
+    fn address(
+        &self,
+        state: &State,
+        mode: i64,
+        word: &Expr,
+        raw: i64,
+    ) -> Result<Option<usize>, SymbolicError> {
+        let base = if mode == 2 { state.relative_base } else { 0 };
+        let address = match word.as_constant().map(|value| base.checked_add(value)) {
+            Some(Some(address)) => address,
+            Some(None) => {
+                return Err(SymbolicError::Vm(VmError::Overflow { ip: state.ip, instruction: raw }));
+            },
+            None => return Ok(None),
+        };
+
+        if address < 0 {
+            return Err(SymbolicError::Vm(VmError::NegativeAddress {
+                ip: state.ip,
+                instruction: raw,
+                address,
+            }));
+        }
+        Ok(Some(address as usize))
+    }
+
+    fn step(&self, state: &mut State) -> Result<Stepped, SymbolicError> {
+        let ip = state.ip;
+        let raw = match self.word(state, ip).as_constant() {
+            Some(raw) => raw,
+            None => return Err(SymbolicError::SymbolicInstruction { ip }),
+        };
+        let opcode = raw % 100;
+        let shape = match &self.isa {
+            Some(isa) => isa.shape(opcode),
+            None => Shape::standard(opcode),
+        };
+        let builtin = match shape {
+            Some(Shape { builtin: Some(builtin), .. }) => builtin,
+            Some(_) => return Err(SymbolicError::Extension { ip }),
+            None => return Err(SymbolicError::Vm(VmError::UnknownOpcode { ip, instruction: raw })),
+        };
+        let len = builtin.param_count() + 1;
+
+        let mut params = vec![];
+        for i in 0..len - 1 {
+            let mode = (raw / 10i64.pow(i as u32 + 2)) % 10;
+            if mode > 2 {
+                let error = VmError::InvalidMode { ip, instruction: raw, mode };
+                return Err(SymbolicError::Vm(error));
+            }
+            params.push((mode, self.word(state, ip + i + 1)));
+        }
+
+        let read = |state: &State, i: usize| -> Result<Expr, SymbolicError> {
+            let (mode, word) = &params[i];
+            if *mode == 1 {
+                return Ok(word.clone());
+            }
+            Ok(match self.address(state, *mode, word, raw)? {
+                Some(addr) => self.word(state, addr),
+                None if *mode == 2 => {
+                    let addr = word.clone() + Expr::constant(state.relative_base);
+                    Expr::atom(Atom::Load(Box::new(addr)))
+                },
+                None => Expr::atom(Atom::Load(Box::new(word.clone()))),
+            })
+        };
+        let target = |state: &State, i: usize| -> Result<usize, SymbolicError> {
+            let (mode, word) = &params[i];
+            if *mode == 1 {
+                return Err(SymbolicError::Vm(VmError::WriteToImmediate { ip, instruction: raw }));
+            }
+            match self.address(state, *mode, word, raw)? {
+                Some(addr) => Ok(addr),
+                None => Err(SymbolicError::SymbolicAddress { ip }),
+            }
+        };
+
+        let next = ip + len;
+        match builtin {
+            Builtin::Add | Builtin::Mul | Builtin::LessThan | Builtin::Equals => {
+                let (a, b) = (read(state, 0)?, read(state, 1)?);
+                let value = match builtin {
+                    Builtin::Add => a + b,
+                    Builtin::Mul => a * b,
+                    Builtin::LessThan => Expr::lt(a, b).substitute(&state.known),
+                    _ => Expr::eq(a, b).substitute(&state.known),
+                };
+                let addr = target(state, 2)?;
+                state.cells.insert(addr, value);
+            },
+            Builtin::Input => {
+                let value = match state.inputs.pop_front() {
+                    Some(value) => value,
+                    None => return Ok(Stepped::Stopped(End::NeedsInput)),
+                };
+                let addr = target(state, 0)?;
+                state.cells.insert(addr, value);
+            },
+            Builtin::Output => {
+                let value = read(state, 0)?;
+                state.outputs.push(value);
+            },
+            Builtin::JumpIfTrue | Builtin::JumpIfFalse => {
+                let jump_if_true = builtin == Builtin::JumpIfTrue;
+                let cond = read(state, 0)?.substitute(&state.known);
+                let jump_to = |state: &State| match read(state, 1)?.as_constant() {
+                    Some(addr) if addr >= 0 => Ok(addr as usize),
+                    Some(address) => Err(SymbolicError::Vm(VmError::NegativeAddress {
+                        ip,
+                        instruction: raw,
+                        address,
+                    })),
+                    None => Err(SymbolicError::SymbolicAddress { ip }),
+                };
+
+                match cond.as_constant() {
+                    Some(value) => {
+                        let taken = (value != 0) == jump_if_true;
+                        state.ip = if taken { jump_to(state)? } else { next };
+                    },
+                    None => {
+                        let mut taken = state.clone();
+                        taken.constrain(cond.clone(), jump_if_true);
+                        taken.ip = jump_to(&taken)?;
+                        taken.steps += 1;
+
+                        state.constrain(cond, !jump_if_true);
+                        state.ip = next;
+                        state.steps += 1;
+                        return Ok(Stepped::Forked(taken));
+                    },
+                }
+                state.steps += 1;
+                return Ok(Stepped::Running);
+            },
+            Builtin::AdjustBase => match read(state, 0)?.as_constant() {
+                Some(delta) => state.relative_base = state.relative_base.wrapping_add(delta),
+                None => return Err(SymbolicError::SymbolicAddress { ip }),
+            },
+            Builtin::Halt => return Ok(Stepped::Stopped(End::Halted)),
+        }
+
+        state.ip = next;
+        state.steps += 1;
+        Ok(Stepped::Running)
+    }
+}
+
+impl Program {
+    /// Starts symbolic execution from the program's current state, with
+    /// its instruction set.  Queued inputs are kept, as constants.
+    pub fn symbolic(&self) -> Symbolic {
+        let state = State {
+            ip: self.ip,
+            relative_base: self.relative_base,
+            cells: BTreeMap::new(),
+            inputs: self.inputs.iter().map(|&value| Expr::constant(value)).collect(),
+            outputs: vec![],
+            constraints: vec![],
+            known: BTreeMap::new(),
+            steps: 0,
+        };
+
+        Symbolic {
+            memory: self.memory.clone(),
+            isa: self.isa.clone(),
+            state,
+            max_paths: DEFAULT_MAX_PATHS,
+            max_steps: DEFAULT_MAX_STEPS,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::isa::Trap;
+
+    #[test]
+    fn day_2_is_solved() {
+        let memory: Vec<i64> = include_str!("../../inputs/02.txt")
+            .trim()
+            .split(',')
+            .map(|x| x.parse().unwrap())
+            .collect();
+        let mut symbolic = Program::new(&memory).symbolic();
+        symbolic.set_symbol(1, "noun");
+        symbolic.set_symbol(2, "verb");
+        let paths = symbolic.run().unwrap();
+        assert_eq!(paths.len(), 1);
+
+        let result = paths[0].read(0);
+        let values: HashMap<String, i64> =
+            vec![(String::from("noun"), 12), (String::from("verb"), 2)].into_iter().collect();
+        assert_eq!(result.eval(&values), Some(4_484_226));
+
+        let bounds = [("noun", 0..100), ("verb", 0..100)];
+        assert_eq!(result.solve(19_690_720, &bounds), Some(vec![56, 96]));
+        assert_eq!(result.solve(-1, &bounds), None);
+    }
+
+    #[test]
+    fn overflow_wraps_or_is_an_error() {
+        // MUL [5], [5] -> [0]; HLT
+        let paths = Program::new(&[2, 5, 5, 0, 99, i64::MAX]).symbolic().run().unwrap();
+        assert_eq!(paths[0].read(0), Expr::constant(i64::MAX.wrapping_mul(i64::MAX)));
+
+        let x = Expr::symbol("x") * Expr::constant(2);
+        let values: HashMap<String, i64> =
+            vec![(String::from("x"), i64::MAX)].into_iter().collect();
+        assert_eq!(x.eval(&values), None);
+        assert_eq!((x + Expr::constant(1)).solve(i64::MIN, &[("x", 0..10)]), None);
+        assert_eq!(Expr::constant(i64::MIN).to_string(), "-9223372036854775808");
+
+        // ARB #max; OUT rb+1
+        let result = Program::new(&[109, i64::MAX, 204, 1, 99]).symbolic().run();
+        assert!(matches!(result, Err(SymbolicError::Vm(VmError::Overflow { ip: 2, .. }))));
+    }
+
+    #[test]
+    fn instruction_set_is_used() {
+        // "MUL" x, #3 -> [0] with ADD and MUL swapped; HLT; TRAP
+        let mut isa = InstructionSet::standard();
+        isa.set_builtin(1, Builtin::Mul);
+        isa.set_builtin(2, Builtin::Add);
+        isa.register(42, Trap);
+        let mut program = Program::new(&[1001, 6, 3, 0, 99, 42, 0]);
+        program.set_instruction_set(isa);
+
+        let mut symbolic = program.symbolic();
+        symbolic.set_symbol(6, "x");
+        let paths = symbolic.run().unwrap();
+        assert_eq!(paths[0].read(0), Expr::symbol("x") * Expr::constant(3));
+
+        program.set_ip(5);
+        let result = program.symbolic().run();
+        assert!(matches!(result, Err(SymbolicError::Extension { ip: 5 })));
+    }
+}
@@ -0,0 +1,360 @@
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+use super::{Instruction, Param};
+use super::cell::Cell;
+
+const MAGIC: &[u8; 4] = b"ICTR";
+const VERSION: u8 = 1;
+
+/// A single executed instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEvent<T = i64> {
+    /// Number of instructions executed before this one
+    pub step: u64,
+    pub ip: usize,
+    /// The raw instruction word, including parameter modes
+    pub instruction: i64,
+    pub params: Vec<Param>,
+    /// The values of all parameters that were read, in order
+    pub reads: Vec<T>,
+    /// The address written to and the value written, if any
+    pub write: Option<(usize, T)>,
+    /// The relative base before the instruction executed
+    pub relative_base: i64,
+}
+
+impl<T: Cell> TraceEvent<T> {
+    pub fn opcode(&self) -> i64 {
+        self.instruction % 100
+    }
+
+    fn text(&self) -> String {
+        Instruction {
+            opcode: self.opcode(),
+            params: self.params.clone(),
+        }.to_string()
+    }
+
+    /// Formats the event as a single line of JSON.
+    pub fn to_json(&self) -> String {
+        let params: Vec<String> = self.params.iter().map(|p| format!("\"{}\"", p)).collect();
+        let reads: Vec<String> = self.reads.iter().map(|r| r.to_string()).collect();
+        let write = match &self.write {
+            Some((addr, value)) => format!("{{\"addr\":{},\"value\":{}}}", addr, value),
+            None => String::from("null"),
+        };
+
+        format!(
+            "{{\"step\":{},\"ip\":{},\"instruction\":{},\"opcode\":{},\"params\":[{}],\"reads\":[{}],\"write\":{},\"rb\":{}}}",
+            self.step,
+            self.ip,
+            self.instruction,
+            self.opcode(),
+            params.join(","),
+            reads.join(","),
+            write,
+            self.relative_base,
+        )
+    }
+}
+
+impl<T: Cell> fmt::Display for TraceEvent<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>8} {:>5}: {:<32} reads={:?}", self.step, self.ip, self.text(), self.reads)?;
+        if let Some((addr, value)) = &self.write {
+            write!(f, " [{}]={}", addr, value)?;
+        }
+        write!(f, " rb={}", self.relative_base)
+    }
+}
+
+/// Receives every instruction executed by a `Program` it is attached to.
+pub trait Tracer<T = i64>: Send {
+    fn trace(&mut self, event: &TraceEvent<T>);
+}
+
+/// Keeps all trace events in memory.
+#[derive(Default)]
+pub struct TraceRecorder<T = i64> {
+    events: Vec<TraceEvent<T>>,
+}
+
+impl<T: Cell> TraceRecorder<T> {
+    pub fn new() -> TraceRecorder<T> {
+        TraceRecorder { events: vec![] }
+    }
+
+    pub fn events(&self) -> &[TraceEvent<T>] {
+        &self.events
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+}
+
+impl<T: Cell> Tracer<T> for TraceRecorder<T> {
+    fn trace(&mut self, event: &TraceEvent<T>) {
+        self.events.push(event.clone());
+    }
+}
+
+/// Streams trace events as JSON lines.  Write errors are kept until the
+/// next call to `flush`, since tracing itself can't fail.
+pub struct JsonLinesWriter<W: Write + Send> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write + Send> JsonLinesWriter<W> {
+    pub fn new(writer: W) -> JsonLinesWriter<W> {
+        JsonLinesWriter { writer, error: None }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.writer.flush(),
+        }
+    }
+}
+
+impl<T: Cell, W: Write + Send> Tracer<T> for JsonLinesWriter<W> {
+    fn trace(&mut self, event: &TraceEvent<T>) {
+        if self.error.is_none() {
+            if let Err(err) = writeln!(self.writer, "{}", event.to_json()) {
+                self.error = Some(err);
+            }
+        }
+    }
+}
+
+/// Streams trace events in a compact binary format, which can be read
+/// back with `read_binary`.
+///
+/// The file starts with the magic bytes `ICTR` and a version byte,
+/// followed by one record per event.  Unsigned numbers are stored as
+/// LEB128 varints and signed ones are zigzag encoded first:
+///
+/// ```text
+/// step ip instruction param_count (mode value)* read_count read* has_write [addr value] rb
+/// ```
+///
+/// where the counts, modes and `has_write` are single bytes.  Values are
+/// stored as `i64`s, so this only traces programs with `i64` cells.
+pub struct BinaryWriter<W: Write + Send> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write + Send> BinaryWriter<W> {
+    pub fn new(mut writer: W) -> io::Result<BinaryWriter<W>> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        Ok(BinaryWriter { writer, error: None })
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.writer.flush(),
+        }
+    }
+
+    fn write_event(&mut self, event: &TraceEvent) -> io::Result<()> {
+        let mut buf = vec![];
+
+        write_varint(&mut buf, event.step);
+        write_varint(&mut buf, event.ip as u64);
+        write_signed(&mut buf, event.instruction);
+
+        buf.push(event.params.len() as u8);
+        for param in &event.params {
+            buf.push(param.mode_code() as u8);
+            write_signed(&mut buf, param.value());
+        }
+
+        buf.push(event.reads.len() as u8);
+        for &value in &event.reads {
+            write_signed(&mut buf, value);
+        }
+
+        match event.write {
+            Some((addr, value)) => {
+                buf.push(1);
+                write_varint(&mut buf, addr as u64);
+                write_signed(&mut buf, value);
+            },
+            None => buf.push(0),
+        }
+
+        write_signed(&mut buf, event.relative_base);
+        self.writer.write_all(&buf)
+    }
+}
+
+impl<W: Write + Send> Tracer for BinaryWriter<W> {
+    fn trace(&mut self, event: &TraceEvent) {
+        if self.error.is_none() {
+            if let Err(err) = self.write_event(event) {
+                self.error = Some(err);
+            }
+        }
+    }
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_signed(buf: &mut Vec<u8>, value: i64) {
+    write_varint(buf, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+struct Decoder<R: Read> {
+    reader: io::Bytes<R>,
+}
+
+impl<R: Read> Decoder<R> {
+    /// Reads a byte, returning `None` at the end of the input.
+    fn try_byte(&mut self) -> io::Result<Option<u8>> {
+        self.reader.next().transpose()
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        match self.try_byte()? {
+            Some(byte) => Ok(byte),
+            None => Err(invalid_data("truncated trace record")),
+        }
+    }
+
+    fn varint_from(&mut self, first: u8) -> io::Result<u64> {
+        let mut value = 0;
+        let mut shift = 0;
+        let mut byte = first;
+
+        loop {
+            if shift > 63 {
+                return Err(invalid_data("varint too long"));
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+            byte = self.byte()?;
+        }
+    }
+
+    fn varint(&mut self) -> io::Result<u64> {
+        let first = self.byte()?;
+        self.varint_from(first)
+    }
+
+    fn signed(&mut self) -> io::Result<i64> {
+        let value = self.varint()?;
+        Ok(((value >> 1) as i64) ^ -((value & 1) as i64))
+    }
+
+    fn event(&mut self) -> io::Result<Option<TraceEvent>> {
+        let step = match self.try_byte()? {
+            Some(first) => self.varint_from(first)?,
+            None => return Ok(None),
+        };
+        let ip = self.varint()? as usize;
+        let instruction = self.signed()?;
+
+        let mut params = vec![];
+        for _ in 0..self.byte()? {
+            let mode = self.byte()? as i64;
+            let value = self.signed()?;
+            match Param::new(value, mode) {
+                Some(param) => params.push(param),
+                None => return Err(invalid_data("invalid parameter mode")),
+            }
+        }
+
+        let mut reads = vec![];
+        for _ in 0..self.byte()? {
+            reads.push(self.signed()?);
+        }
+
+        let write = match self.byte()? {
+            0 => None,
+            _ => Some((self.varint()? as usize, self.signed()?)),
+        };
+        let relative_base = self.signed()?;
+
+        Ok(Some(TraceEvent { step, ip, instruction, params, reads, write, relative_base }))
+    }
+}
+
+/// Reads a trace written by `BinaryWriter`.
+pub fn read_binary<R: BufRead>(reader: R) -> io::Result<Vec<TraceEvent>> {
+    let mut decoder = Decoder { reader: reader.bytes() };
+
+    let mut header = [0; 5];
+    for byte in header.iter_mut() {
+        *byte = decoder.byte()?;
+    }
+    if &header[..4] != MAGIC {
+        return Err(invalid_data("not an intcode trace"));
+    }
+    if header[4] != VERSION {
+        return Err(invalid_data("unsupported trace version"));
+    }
+
+    let mut events = vec![];
+    while let Some(event) = decoder.event()? {
+        events.push(event);
+    }
+
+    Ok(events)
+}
+
+/// Returns the index of the first event where two traces differ, or `None`
+/// if they are identical.
+pub fn first_divergence(a: &[TraceEvent], b: &[TraceEvent]) -> Option<usize> {
+    match a.iter().zip(b).position(|(x, y)| x != y) {
+        Some(index) => Some(index),
+        None if a.len() != b.len() => Some(a.len().min(b.len())),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::intcode::Program;
+
+    #[test]
+    fn binary_trace_round_trip() {
+        // IN -> [9]; MUL [9], #-2 -> [10]; OUT [10]; HLT
+        let mut program = Program::new(&[3, 9, 1002, 9, -2, 10, 4, 10, 99]);
+        let recorder = Arc::new(Mutex::new(TraceRecorder::new()));
+        program.set_tracer(recorder.clone());
+        program.set_input(21);
+        program.run().unwrap();
+
+        let events = recorder.lock().unwrap().events().to_vec();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[1].reads, [21, -2]);
+        assert_eq!(events[1].write, Some((10, -42)));
+
+        let mut writer = BinaryWriter::new(vec![]).unwrap();
+        for event in &events {
+            writer.trace(event);
+        }
+        writer.flush().unwrap();
+        assert_eq!(read_binary(&writer.writer[..]).unwrap(), events);
+    }
+}
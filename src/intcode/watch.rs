@@ -0,0 +1,120 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+use super::Program;
+use super::cell::Cell;
+
+/// A memory access made by an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Which accesses a watchpoint fires on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watch {
+    Reads,
+    Writes,
+    All,
+}
+
+impl Watch {
+    pub fn covers(&self, access: Access) -> bool {
+        matches!(
+            (self, access),
+            (Watch::All, _) | (Watch::Reads, Access::Read) | (Watch::Writes, Access::Write)
+        )
+    }
+}
+
+/// A watched memory cell being read or written.  For reads, `old` and
+/// `new` are both the value read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchEvent<T = i64> {
+    pub access: Access,
+    pub addr: usize,
+    /// Address of the instruction making the access
+    pub ip: usize,
+    pub old: T,
+    pub new: T,
+}
+
+impl<T: fmt::Display> fmt::Display for WatchEvent<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.access {
+            Access::Read => write!(f, "read [{}] = {} at ip {}", self.addr, self.new, self.ip),
+            Access::Write => {
+                write!(f, "write [{}] {} -> {} at ip {}", self.addr, self.old, self.new, self.ip)
+            },
+        }
+    }
+}
+
+pub type Callback<T = i64> = Arc<Mutex<dyn FnMut(&WatchEvent<T>) + Send>>;
+
+#[derive(Clone)]
+struct Watchpoint<T> {
+    id: usize,
+    addrs: RangeInclusive<usize>,
+    watch: Watch,
+    callback: Callback<T>,
+}
+
+#[derive(Clone)]
+pub(super) struct Watchpoints<T> {
+    points: Vec<Watchpoint<T>>,
+    next_id: usize,
+}
+
+impl<T> Default for Watchpoints<T> {
+    fn default() -> Watchpoints<T> {
+        Watchpoints { points: vec![], next_id: 0 }
+    }
+}
+
+impl<T> Watchpoints<T> {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn notify(&self, event: WatchEvent<T>) {
+        for point in &self.points {
+            if point.watch.covers(event.access) && point.addrs.contains(&event.addr) {
+                (point.callback.lock().unwrap())(&event);
+            }
+        }
+    }
+}
+
+impl<T: Cell> Program<T> {
+    /// Calls `callback` whenever an instruction reads or writes an address
+    /// in `addrs`, and returns an id for removing the watchpoint again.
+    /// Immediate parameters don't count as reads, and neither do accesses
+    /// made through `read` and `write`.
+    pub fn add_watchpoint(
+        &mut self,
+        addrs: RangeInclusive<usize>,
+        watch: Watch,
+        callback: Callback<T>,
+    ) -> usize {
+        let watchpoints = &mut self.watchpoints;
+        let id = watchpoints.next_id;
+        watchpoints.next_id += 1;
+        watchpoints.points.push(Watchpoint { id, addrs, watch, callback });
+        id
+    }
+
+    /// Removes a watchpoint, returning false if there was none with that id.
+    pub fn remove_watchpoint(&mut self, id: usize) -> bool {
+        let points = &mut self.watchpoints.points;
+        let count = points.len();
+        points.retain(|point| point.id != id);
+        points.len() < count
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.points.clear();
+    }
+}
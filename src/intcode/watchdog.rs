@@ -0,0 +1,164 @@
+use std::time::{Duration, Instant};
+use super::{Program, VmError};
+use super::cell::Cell;
+use super::memory::Memory;
+
+/// How often the clock is checked, in instructions.
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// Limits for `Program::run_with_limits`.  All of them are off by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum number of instructions to execute
+    pub instructions: Option<u64>,
+    /// Maximum wall-clock time to run for
+    pub time: Option<Duration>,
+    /// Stop when the program gets back to a state it has already been in
+    /// without doing any I/O in between, which means it will never halt.
+    /// Ignored while devices are mapped or the instruction set has custom
+    /// instructions, since those can bring in data from outside.
+    pub detect_loops: bool,
+}
+
+/// Why `Program::run_with_limits` returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Halted,
+    InstructionLimit,
+    TimeLimit,
+    /// The program is stuck in a loop passing through `ip`, repeating every
+    /// `period` instructions
+    Loop { ip: usize, period: u64 },
+}
+
+/// The part of the program state that decides what it does next.  Input
+/// and output queue lengths stand in for the queues themselves, which is
+/// enough as long as nothing is added or removed from outside while
+/// running.  Devices and custom instructions like host calls can do just
+/// that, or return something different each time, so loops aren't looked
+/// for while there are any.
+struct State<T: Cell> {
+    ip: usize,
+    relative_base: i64,
+    inputs: usize,
+    outputs: usize,
+    memory: Memory<T>,
+}
+
+impl<T: Cell> State<T> {
+    fn of(program: &Program<T>) -> State<T> {
+        State {
+            ip: program.ip,
+            relative_base: program.relative_base,
+            inputs: program.inputs.len(),
+            outputs: program.outputs.len(),
+            memory: program.memory.clone(),
+        }
+    }
+
+    /// Cheap checks first, so memory is only compared when everything
+    /// else matches.
+    fn matches(&self, program: &Program<T>) -> bool {
+        self.ip == program.ip
+            && self.relative_base == program.relative_base
+            && self.inputs == program.inputs.len()
+            && self.outputs == program.outputs.len()
+            && self.memory == program.memory
+    }
+}
+
+impl<T: Cell> Program<T> {
+    /// Runs until the program halts or has executed `max_instructions`
+    /// instructions.
+    pub fn run_with_limit(&mut self, max_instructions: u64) -> Result<Outcome, VmError> {
+        self.run_with_limits(&Limits {
+            instructions: Some(max_instructions),
+            ..Limits::default()
+        })
+    }
+
+    /// Runs until the program halts or one of the limits is hit.
+    ///
+    /// Loops are found with Brent's algorithm: the state is saved at
+    /// exponentially growing intervals and compared against after every
+    /// step.  Saving is cheap since memory pages are shared until written.
+    pub fn run_with_limits(&mut self, limits: &Limits) -> Result<Outcome, VmError> {
+        let started = Instant::now();
+        let mut executed = 0;
+        let mut saved = State::of(self);
+        let mut power = 1;
+        let mut period = 0;
+        let detect_loops = limits.detect_loops && self.is_closed();
+
+        while !self.halted {
+            if limits.instructions.is_some_and(|max| executed >= max) {
+                return Ok(Outcome::InstructionLimit);
+            }
+            if let Some(time) = limits.time {
+                if executed % CLOCK_INTERVAL == 0 && started.elapsed() >= time {
+                    return Ok(Outcome::TimeLimit);
+                }
+            }
+
+            self.step()?;
+            executed += 1;
+
+            if detect_loops {
+                period += 1;
+                if saved.matches(self) {
+                    return Ok(Outcome::Loop { ip: self.ip, period });
+                }
+                if period == power {
+                    saved = State::of(self);
+                    power *= 2;
+                    period = 0;
+                }
+            }
+        }
+
+        Ok(Outcome::Halted)
+    }
+
+    /// Whether nothing but the input queue brings data in from outside.
+    fn is_closed(&self) -> bool {
+        let extensions = match &self.isa {
+            Some(isa) => isa.opcodes().iter().any(|&opcode| isa.builtin(opcode).is_none()),
+            None => false,
+        };
+        self.devices.is_empty() && !extensions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::intcode::device::Timer;
+
+    fn limits() -> Limits {
+        Limits { instructions: Some(10_000), detect_loops: true, ..Limits::default() }
+    }
+
+    #[test]
+    fn loop_is_detected() {
+        // JT #1, #0
+        let mut program: Program = Program::new(&[1105, 1, 0]);
+        assert_eq!(program.run_with_limits(&limits()), Ok(Outcome::Loop { ip: 0, period: 1 }));
+    }
+
+    #[test]
+    fn counting_loop_halts() {
+        // ADD [12], #1 -> [12]; LT [12], #100 -> [13]; JT [13], #0; HLT
+        let memory = [1001, 12, 1, 12, 1007, 12, 100, 13, 1005, 13, 0, 99, 0, 0];
+        let mut program: Program = Program::new(&memory);
+        assert_eq!(program.run_with_limits(&limits()), Ok(Outcome::Halted));
+    }
+
+    #[test]
+    fn loops_are_not_looked_for_with_devices() {
+        // Waits for the timer at [100] to pass a second
+        let mut program: Program = Program::new(&[1007, 100, 1000, 101, 1005, 101, 0, 99]);
+        program.map_device(100..101, Arc::new(Mutex::new(Timer::new())));
+        assert_eq!(program.run_with_limits(&limits()), Ok(Outcome::InstructionLimit));
+    }
+}